@@ -0,0 +1,47 @@
+vec3 background_solid(in vec3 ray, in vec3 color){
+    return color;
+}
+
+vec3 background_gradient(in vec3 ray, in vec3 horizon, in vec3 zenith){
+    return mix(horizon, zenith, clamp(ray.y, 0.0, 1.0));
+}
+
+vec3 background_sky(in vec3 ray, in vec3 sun_direction, in vec3 sun_color){
+    const vec3 zenith = vec3(0.25, 0.45, 0.85);
+    const vec3 horizon = vec3(0.75, 0.85, 0.95);
+    const vec3 ground = vec3(0.3, 0.28, 0.25);
+
+    vec3 sky = ray.y > 0.0
+        ? mix(horizon, zenith, sqrt(ray.y))
+        : mix(horizon, ground, pow(clamp(-ray.y, 0.0, 1.0), 0.3));
+
+    // Wide glow around the sun and a sharp disk on top of it
+    float sun = max(dot(ray, sun_direction), 0.0);
+    sky += sun_color * (pow(sun, 8.0) * 0.2 + pow(sun, 64.0) * 0.3);
+    sky += sun_color * smoothstep(0.9995, 0.9998, sun);
+    return sky;
+}
+
+// Coordinate of the ray in a strip of cube faces laid out left to right as +X, -X, +Y, -Y, +Z, -Z,
+// with the top row of every face first
+vec2 cubemap_strip_uv(in vec3 ray){
+    vec3 a = abs(ray);
+    float face;
+    vec2 uv;
+    if (a.x >= a.y && a.x >= a.z){
+        face = ray.x > 0.0 ? 0.0 : 1.0;
+        uv = vec2(ray.x > 0.0 ? -ray.z : ray.z, -ray.y) / a.x;
+    }
+    else if (a.y >= a.z){
+        face = ray.y > 0.0 ? 2.0 : 3.0;
+        uv = vec2(ray.x, ray.y > 0.0 ? ray.z : -ray.z) / a.y;
+    }
+    else{
+        face = ray.z > 0.0 ? 4.0 : 5.0;
+        uv = vec2(ray.z > 0.0 ? ray.x : -ray.x, -ray.y) / a.z;
+    }
+
+    // Keep filtering from bleeding into the neighbouring face
+    uv = clamp(uv * 0.5 + 0.5, vec2(0.002), vec2(0.998));
+    return vec2((face + uv.x) / 6.0, uv.y);
+}
\ No newline at end of file
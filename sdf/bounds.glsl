@@ -0,0 +1,39 @@
+// Bound methods return the [tmin, tmax] interval of the ray in which their sdf can come closer than
+// HIT_DISTANCE, tmin > tmax means the ray misses. Intervals may be larger than needed, but never smaller.
+const float BOUND_FAR = 1e30;
+const vec2 BOUND_MISS = vec2(BOUND_FAR, -BOUND_FAR);
+// Shapes are inflated by twice the hit distance, so rounding at the edges of the interval can't cull a hit
+const float BOUND_MARGIN = 2.0 * HIT_DISTANCE;
+
+// Older bound methods return whether the ray can hit at all
+vec2 as_interval(bool hitable){
+    return hitable ? vec2(0.0, BOUND_FAR) : BOUND_MISS;
+}
+
+vec2 as_interval(vec2 interval){
+    return interval;
+}
+
+vec2 bound_ray_sphere(in vec3 origin, in vec3 ray, in vec3 center, float radius){
+    vec3 L = origin - center;
+    float r = radius + BOUND_MARGIN;
+    float b = dot(L,ray);
+    float disc = b*b - (dot(L,L) - r*r);
+    if (disc < 0.0) return BOUND_MISS;
+
+    float s = sqrt(disc);
+    return vec2(max(-b - s, 0.0), -b + s);
+}
+
+vec2 bound_ray_box(in vec3 origin, in vec3 ray, in vec3 center, in vec3 half_size){
+    // Axis parallel rays get a tiny slope so the slab test never divides by zero
+    vec3 safe_ray = mix(ray, vec3(0.00000001), lessThan(abs(ray), vec3(0.00000001)));
+    vec3 size = half_size + BOUND_MARGIN;
+    vec3 t0 = (center - size - origin) / safe_ray;
+    vec3 t1 = (center + size - origin) / safe_ray;
+    vec3 near = min(t0,t1);
+    vec3 far = max(t0,t1);
+    return vec2(
+        max(max(max(near.x,near.y),near.z), 0.0),
+        min(min(far.x,far.y),far.z));
+}
\ No newline at end of file
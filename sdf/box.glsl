@@ -0,0 +1,12 @@
+float sdf_box(in vec3 position, in vec3 center, in vec3 half_size){
+    vec3 q = abs(position - center) - half_size;
+    return length(max(q,0.0)) + min(max(q.x,max(q.y,q.z)),0.0);
+}
+
+vec2 bound_box(in vec3 origin, in vec3 ray, in vec3 center, in vec3 half_size){
+    return bound_ray_box(origin, ray, center, half_size);
+}
+
+Material color_box(in vec3 position, in vec3 color){
+    return material_diffuse(color);
+}
\ No newline at end of file
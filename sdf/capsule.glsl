@@ -0,0 +1,13 @@
+float sdf_capsule(in vec3 position, in vec3 center, float half_height, float radius){
+    vec3 p = position - center;
+    p.y -= clamp(p.y, -half_height, half_height);
+    return length(p) - radius;
+}
+
+vec2 bound_capsule(in vec3 origin, in vec3 ray, in vec3 center, float half_height, float radius){
+    return bound_ray_box(origin, ray, center, vec3(radius, half_height + radius, radius));
+}
+
+Material color_capsule(in vec3 position, in vec3 color){
+    return material_diffuse(color);
+}
\ No newline at end of file
@@ -0,0 +1,19 @@
+// Cone standing on its base, with the tip half_height above the center
+float sdf_cone(in vec3 position, in vec3 center, float half_height, float radius){
+    vec3 p = position - center;
+    vec2 q = vec2(length(p.xz), p.y);
+    vec2 k1 = vec2(0.0, half_height);
+    vec2 k2 = vec2(-radius, 2.0 * half_height);
+    vec2 ca = vec2(q.x - min(q.x, q.y < 0.0 ? radius : 0.0), abs(q.y) - half_height);
+    vec2 cb = q - k1 + k2 * clamp(dot(k1 - q, k2) / dot(k2,k2), 0.0, 1.0);
+    float s = (cb.x < 0.0 && ca.y < 0.0) ? -1.0 : 1.0;
+    return s * sqrt(min(dot(ca,ca), dot(cb,cb)));
+}
+
+vec2 bound_cone(in vec3 origin, in vec3 ray, in vec3 center, float half_height, float radius){
+    return bound_ray_box(origin, ray, center, vec3(radius, half_height, radius));
+}
+
+Material color_cone(in vec3 position, in vec3 color){
+    return material_diffuse(color);
+}
\ No newline at end of file
@@ -0,0 +1,13 @@
+float sdf_cylinder(in vec3 position, in vec3 center, float half_height, float radius){
+    vec3 p = position - center;
+    vec2 d = abs(vec2(length(p.xz), p.y)) - vec2(radius, half_height);
+    return min(max(d.x,d.y),0.0) + length(max(d,0.0));
+}
+
+vec2 bound_cylinder(in vec3 origin, in vec3 ray, in vec3 center, float half_height, float radius){
+    return bound_ray_box(origin, ray, center, vec3(radius, half_height, radius));
+}
+
+Material color_cylinder(in vec3 position, in vec3 color){
+    return material_diffuse(color);
+}
\ No newline at end of file
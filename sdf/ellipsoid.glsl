@@ -0,0 +1,15 @@
+// Distance in the space where the ellipsoid is a unit sphere, scaled back by the smallest radius.
+// Exact for equal radii, otherwise it stays below the true distance so thin ellipsoids only take smaller steps.
+float sdf_ellipsoid(in vec3 position, in vec3 center, in vec3 radii){
+    vec3 p = position - center;
+    return (length(p / radii) - 1.0) * min(radii.x, min(radii.y, radii.z));
+}
+
+vec2 bound_ellipsoid(in vec3 origin, in vec3 ray, in vec3 center, in vec3 radii){
+    // The sdf reaches HIT_DISTANCE on the ellipsoid grown by HIT_DISTANCE over the smallest radius
+    return bound_ray_box(origin, ray, center, radii * (1.0 + HIT_DISTANCE / min(radii.x, min(radii.y, radii.z))));
+}
+
+Material color_ellipsoid(in vec3 position, in vec3 color){
+    return material_diffuse(color);
+}
\ No newline at end of file
@@ -0,0 +1,20 @@
+// Hexagonal prism along the y axis, radius is measured to the middle of the sides
+float sdf_hex_prism(in vec3 position, in vec3 center, float radius, float half_height){
+    const vec3 k = vec3(-0.8660254, 0.5, 0.57735);
+    vec3 p = abs(position - center).xzy;
+    p.xy -= 2.0 * min(dot(k.xy, p.xy), 0.0) * k.xy;
+    vec2 d = vec2(
+        length(p.xy - vec2(clamp(p.x, -k.z * radius, k.z * radius), radius)) * sign(p.y - radius),
+        p.z - half_height);
+    return min(max(d.x,d.y),0.0) + length(max(d,0.0));
+}
+
+vec2 bound_hex_prism(in vec3 origin, in vec3 ray, in vec3 center, float radius, float half_height){
+    // Distance to the corners
+    float outer = radius * 1.1547005;
+    return bound_ray_box(origin, ray, center, vec3(outer, half_height, outer));
+}
+
+Material color_hex_prism(in vec3 position, in vec3 color){
+    return material_diffuse(color);
+}
\ No newline at end of file
@@ -0,0 +1,3 @@
+Material material_constant(in vec3 position, in vec3 albedo, float roughness, float metallic, in vec3 emission, float opacity, float ior){
+    return Material(albedo, roughness, metallic, emission, opacity, ior);
+}
\ No newline at end of file
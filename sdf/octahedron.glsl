@@ -0,0 +1,14 @@
+// Distance to the plane of the nearest face, exact in front of the faces and too small near edges and vertices
+float sdf_octahedron(in vec3 position, in vec3 center, float size){
+    vec3 p = abs(position - center);
+    return (p.x + p.y + p.z - size) * 0.57735027;
+}
+
+vec2 bound_octahedron(in vec3 origin, in vec3 ray, in vec3 center, float size){
+    // The sdf is scaled down by up to sqrt(3) outside the faces
+    return bound_ray_sphere(origin, ray, center, size + HIT_DISTANCE);
+}
+
+Material color_octahedron(in vec3 position, in vec3 color){
+    return material_diffuse(color);
+}
\ No newline at end of file
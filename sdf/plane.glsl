@@ -2,15 +2,17 @@ float sdf_plane(in vec3 position, in vec3 normal, float height){
     return dot(position,normal) - height;
 }
 
-bool bound_plane(in vec3 origin, in vec3 ray, in vec3 normal,float height){
-    float denom = dot(ray,normal);
-    if (abs(denom) <= 0.0001){
-        return false;
+vec2 bound_plane(in vec3 origin, in vec3 ray, in vec3 normal,float height){
+    // The sdf changes linearly along the ray, so solve for where it drops below the hit distance
+    float start = sdf_plane(origin, normal, height) - BOUND_MARGIN;
+    float slope = dot(ray,normal);
+    if (start <= 0.0){
+        return vec2(0.0, slope > 0.0 ? -start / slope : BOUND_FAR);
     }
-    else{
-        float dist = dot(vec3(0.0,height,0.0) - origin, normal) / denom;
-        return dist >= 0.0001 && dist < 1000.0;
+    if (slope >= 0.0){
+        return BOUND_MISS;
     }
+    return vec2(-start / slope, BOUND_FAR);
 }
 
 vec4 color_plane(in vec3 position){
@@ -0,0 +1,14 @@
+float sdf_segment(in vec3 position, in vec3 start, in vec3 end, float radius){
+    vec3 pa = position - start;
+    vec3 ba = end - start;
+    float h = clamp(dot(pa,ba) / dot(ba,ba), 0.0, 1.0);
+    return length(pa - ba * h) - radius;
+}
+
+vec2 bound_segment(in vec3 origin, in vec3 ray, in vec3 start, in vec3 end, float radius){
+    return bound_ray_box(origin, ray, (start + end) * 0.5, abs(end - start) * 0.5 + radius);
+}
+
+Material color_segment(in vec3 position, in vec3 color){
+    return material_diffuse(color);
+}
\ No newline at end of file
@@ -2,14 +2,8 @@ float sdf_sphere(in vec3 position, in vec3 center, float radius){
     return distance(position,center) - radius;
 }
 
-bool bound_sphere(in vec3 origin, in vec3 ray, in vec3 center, float radius){
-    vec3 L = center - origin;
-    float vl = dot(L,ray);
-
-    if (vl < 0.0) return false;  
-
-    float d = dot(L,L) - vl*vl;
-    return d <= radius*radius;
+vec2 bound_sphere(in vec3 origin, in vec3 ray, in vec3 center, float radius){
+    return bound_ray_sphere(origin, ray, center, radius);
 }
 
 
@@ -0,0 +1,18 @@
+// Projects the texture along the three axes and blends by how much the surface faces each of them
+vec4 triplanar_texture(int slot, in vec3 position, float scale){
+    vec3 weights = pow(abs(hit_normal), vec3(4.0));
+    weights /= weights.x + weights.y + weights.z;
+    return sample_texture(slot, position.zy * scale) * weights.x
+        + sample_texture(slot, position.xz * scale) * weights.y
+        + sample_texture(slot, position.xy * scale) * weights.z;
+}
+
+// Projects the texture onto the plane spanned by u_axis and v_axis from origin, covering one unit of each axis.
+// Outside of that square the alpha is 0, so decals can be mixed over another color.
+vec4 project_texture(int slot, in vec3 position, in vec3 origin, in vec3 u_axis, in vec3 v_axis){
+    vec3 offset = position - origin;
+    vec2 uv = vec2(dot(offset, u_axis) / dot(u_axis, u_axis), dot(offset, v_axis) / dot(v_axis, v_axis));
+    vec4 color = sample_texture(slot, uv);
+    bool inside = all(greaterThanEqual(uv, vec2(0.0))) && all(lessThanEqual(uv, vec2(1.0)));
+    return inside ? color : vec4(color.rgb, 0.0);
+}
\ No newline at end of file
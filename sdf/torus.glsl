@@ -0,0 +1,14 @@
+float sdf_torus(in vec3 position, in vec3 center, float major_radius, float minor_radius){
+    vec3 p = position - center;
+    vec2 q = vec2(length(p.xz) - major_radius, p.y);
+    return length(q) - minor_radius;
+}
+
+vec2 bound_torus(in vec3 origin, in vec3 ray, in vec3 center, float major_radius, float minor_radius){
+    float outer = major_radius + minor_radius;
+    return bound_ray_box(origin, ray, center, vec3(outer, minor_radius, outer));
+}
+
+Material color_torus(in vec3 position, in vec3 color){
+    return material_diffuse(color);
+}
\ No newline at end of file
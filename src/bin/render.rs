@@ -0,0 +1,108 @@
+//! Renders a scene description to a PNG with the CPU backend, without opening a window.
+//!
+//! `render <scene file> <output png> [--size WIDTHxHEIGHT] [--camera x,y,z] [--time seconds] [--threads count]`
+
+use std::{fs, process::ExitCode, time::Instant};
+
+use miniquad_raytrace::renderer::{cpu::{backend::CpuBackend, methods::CpuMethods}, scene_file::SceneFile};
+
+const USAGE: &str = "Usage: render <scene file> <output png> [--size WIDTHxHEIGHT] [--camera x,y,z] [--time seconds] [--threads count]";
+
+struct Options{
+    scene: String,
+    output: String,
+    size: (u32, u32),
+    camera: Option<[f32;3]>,
+    time: f32,
+    threads: Option<usize>
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String>{
+    let mut paths = Vec::new();
+    let mut options = Options{
+        scene: String::new(),
+        output: String::new(),
+        size: (800, 600),
+        camera: None,
+        time: 0.0,
+        threads: None
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next(){
+        if !arg.starts_with("--") {
+            paths.push(arg.clone());
+            continue;
+        }
+        let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+        let invalid = || format!("Invalid value '{}' for {}", value, arg);
+        match arg.as_str(){
+            "--size" => {
+                let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+                options.size = (width.parse().map_err(|_| invalid())?, height.parse().map_err(|_| invalid())?);
+                if options.size.0 == 0 || options.size.1 == 0 {
+                    return Err(invalid());
+                }
+            },
+            "--camera" => {
+                let values = value.split(',').map(|x| x.trim().parse::<f32>()).collect::<Result<Vec<_>, _>>().map_err(|_| invalid())?;
+                options.camera = Some(values.try_into().map_err(|_| invalid())?);
+            },
+            "--time" => options.time = value.parse().map_err(|_| invalid())?,
+            "--threads" => options.threads = Some(value.parse().map_err(|_| invalid())?),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    let [scene, output]: [String;2] = paths.try_into().map_err(|_| "Expected a scene file and an output path".to_string())?;
+    options.scene = scene;
+    options.output = output;
+    Ok(options)
+}
+
+fn run(options: Options) -> Result<(), String>{
+    let source = fs::read_to_string(&options.scene).map_err(|e| format!("Can't read {}: {}", options.scene, e))?;
+
+    let mut methods = CpuMethods::new();
+    let library = methods.register_primitive_library();
+    let mut file = SceneFile::parse(&source, &library).map_err(|e| format!("{}: {}", options.scene, e))?;
+
+    let mut backend = CpuBackend::new(methods);
+    backend.load_scene(&file.take_scene()).map_err(|e| format!("{}: {}", options.scene, e))?;
+    backend.set_position(options.camera.unwrap_or(file.camera));
+    backend.set_elapsed(options.time);
+    if let Some(background) = file.background.take(){
+        backend.set_background(background);
+    }
+    if let Some(density) = file.fog_density{
+        backend.set_fog_density(density);
+    }
+    if let Some(depth) = file.bounce_depth{
+        backend.set_bounce_depth(depth);
+    }
+    if let Some(threads) = options.threads{
+        backend.set_threads(threads);
+    }
+
+    let start = Instant::now();
+    let image = backend.render(options.size.0, options.size.1);
+    image.save_png(&options.output).map_err(|e| format!("Can't write {}: {}", options.output, e))?;
+    println!("Rendered {} at {}x{} in {:.2}s", options.output, options.size.0, options.size.1, start.elapsed().as_secs_f32());
+    Ok(())
+}
+
+fn main() -> ExitCode{
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.iter().any(|x| x == "--help" || x == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    match parse_options(&args).and_then(run){
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            ExitCode::FAILURE
+        },
+    }
+}
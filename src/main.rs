@@ -1,18 +1,25 @@
-use std::{path::PathBuf, str::FromStr, collections::{HashMap, HashSet}};
+use std::{path::PathBuf, str::FromStr, collections::HashSet};
 
-use miniquad::{conf::Conf, EventHandler, Context, UserData, Pipeline, RenderPass, Texture, TextureParams, Buffer, BufferType, Bindings, Shader, ShaderMeta, UniformBlockLayout, BufferLayout, VertexAttribute, VertexFormat, PassAction, FilterMode, KeyMods, KeyCode};
-use miniquad_raytrace::renderer::{Renderer, methods::{MethodDefinition, DataDeserializer, DataEntry}, scene::{SimpleScene, SceneInstance, Serializeable, Scene}, algorithms::{ScaledEstimateBackend, FullSizeBackend, RayMarcherBackend}, App};
+use miniquad::{conf::Conf, Context, KeyCode};
+use miniquad_raytrace::renderer::{Renderer, methods::{MethodDefinition, DataDeserializer, DataEntry}, scene::{SimpleScene, Serializeable, Material, SceneNode, CsgOp, Transform, DomainModifier, Animation}, algorithms::{SwitchableBackend, ScaledEstimateBackend, DynamicResolution, RayMarcherBackend, DebugView}, primitives::Primitive, background::Background, bvh::Aabb, stats::{FrameStats, FrameMetric}, App};
+
+/// Rom words of the tex record, the method id followed by the material data.
+fn material_tex_len() -> u32{
+    DataDeserializer::material().data_len() as u32 + 1
+}
 
 struct SimpleSphere{
     pos: [f32;3],
-    radius: f32
+    radius: f32,
+    material: Material
 }
 
 impl SimpleSphere{
-    pub fn new(pos: [f32;3],radius: f32) -> Self{
+    pub fn new(pos: [f32;3],radius: f32, material: Material) -> Self{
         Self{
             pos,
-            radius
+            radius,
+            material
         }
     }
 }
@@ -25,21 +32,27 @@ impl Serializeable for SimpleSphere{
         serializer.write_value(1); // Sdf Id
         self.pos.serialize(serializer); // Sdf Data
         self.radius.serialize(serializer);
-        serializer.write_value(4); // Tex Len
-        serializer.write_value(1); // Tex Id
-        [0.0f32,1.0,1.0].serialize(serializer);
+        serializer.write_value(material_tex_len()); // Tex Len
+        serializer.write_value(3); // Tex Id
+        self.material.serialize(serializer); // Tex Data
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        Some(Aabb::from_radius(self.pos, self.radius))
     }
 }
 
 struct SimplePlane{
     normal: [f32;3],
-    height: f32
+    height: f32,
+    material: Material
 }
 impl SimplePlane{
-    pub fn new(normal: [f32;3], height: f32) -> Self{
+    pub fn new(normal: [f32;3], height: f32, material: Material) -> Self{
         Self{
             normal,
-            height
+            height,
+            material
         }
     }
 }
@@ -52,8 +65,9 @@ impl Serializeable for SimplePlane{
         serializer.write_value(2); // Sdf Id
         self.normal.serialize(serializer); // Sdf Data
         self.height.serialize(serializer);
-        serializer.write_value(1); // Tex Len
-        serializer.write_value(2); // Tex Id
+        serializer.write_value(material_tex_len()); // Tex Len
+        serializer.write_value(3); // Tex Id
+        self.material.serialize(serializer); // Tex Data
     }
 }
 
@@ -61,63 +75,112 @@ struct Logic{
     key_map: HashSet<KeyCode>,
     position: [f32;3],
     rotation: [f32;4],
+    debug_view: Option<DebugView>,
+    switch_backend: bool,
+    toggle_dynamic_resolution: bool,
+    print_stats: bool,
 }
 
-impl<B: RayMarcherBackend> App<SimpleScene, B> for Logic{
-    fn init(&mut self, renderer: &mut Renderer<SimpleScene, B, Self>)
+impl App<SimpleScene, SwitchableBackend> for Logic{
+    fn init(&mut self, renderer: &mut Renderer<SimpleScene, SwitchableBackend, Self>)
         where Self: Sized {
             renderer.add_methods(MethodDefinition::File(PathBuf::from_str("./sdf/plane.glsl").unwrap()));
             renderer.add_methods(MethodDefinition::File(PathBuf::from_str("./sdf/sphere.glsl").unwrap()));
-
-            let bound_sphere_id = renderer.register_bound_method("bound_sphere".to_string(), DataDeserializer{
-                entries: vec![
-                    DataEntry{ name: "center".into(), type_: miniquad::UniformType::Float3 },
-                    DataEntry{ name: "radius".into(), type_: miniquad::UniformType::Float1 }
-                ]
-            });
-
-            let bound_plane_id = renderer.register_bound_method("bound_plane".to_string(), DataDeserializer{
-                entries: vec![
-                    DataEntry{ name: "normal".into(), type_: miniquad::UniformType::Float3 },
-                    DataEntry{ name: "height".into(), type_: miniquad::UniformType::Float1 }
-                ]
-            });
-
-            let sphere_id = renderer.register_sdf_method("sdf_sphere".into(), DataDeserializer{
-                entries: vec![
-                    DataEntry{ name: "center".into(), type_: miniquad::UniformType::Float3 },
-                    DataEntry{ name: "radius".into(), type_: miniquad::UniformType::Float1 }
-                ]
-            });
-
-            let plane_id = renderer.register_sdf_method("sdf_plane".into(), DataDeserializer{
-                entries: vec![
-                    DataEntry{ name: "normal".into(), type_: miniquad::UniformType::Float3 },
-                    DataEntry{ name: "height".into(), type_: miniquad::UniformType::Float1 }
-                ]
-            });
-
-            let tex_sphere_id = renderer.register_tex_method("color_sphere".to_string(), DataDeserializer { 
-                entries: vec![
-                    DataEntry{ name: "sph_color".into(), type_: miniquad::UniformType::Float3 }
-                ]
-            });
-
-            let tex_plane_id = renderer.register_tex_method("color_plane".to_string(), DataDeserializer { 
-                entries: vec![]
-            });
+            renderer.add_methods(MethodDefinition::File(PathBuf::from_str("./sdf/material.glsl").unwrap()));
+            renderer.set_background(Background::Sky{ sun_direction: [0.3,0.8,-0.5], sun_color: [1.0,0.9,0.7] });
+            renderer.set_fog_density(0.01);
+
+            let _bound_sphere_id = renderer.register_bound_method("bound_sphere".to_string(), DataDeserializer::new(vec![
+                DataEntry{ name: "center".into(), type_: miniquad::UniformType::Float3 },
+                DataEntry{ name: "radius".into(), type_: miniquad::UniformType::Float1 }
+            ]));
+
+            let _bound_plane_id = renderer.register_bound_method("bound_plane".to_string(), DataDeserializer::new(vec![
+                DataEntry{ name: "normal".into(), type_: miniquad::UniformType::Float3 },
+                DataEntry{ name: "height".into(), type_: miniquad::UniformType::Float1 }
+            ]));
+
+            let _sphere_id = renderer.register_sdf_method("sdf_sphere".into(), DataDeserializer::new(vec![
+                DataEntry{ name: "center".into(), type_: miniquad::UniformType::Float3 },
+                DataEntry{ name: "radius".into(), type_: miniquad::UniformType::Float1 }
+            ]));
+
+            let _plane_id = renderer.register_sdf_method("sdf_plane".into(), DataDeserializer::new(vec![
+                DataEntry{ name: "normal".into(), type_: miniquad::UniformType::Float3 },
+                DataEntry{ name: "height".into(), type_: miniquad::UniformType::Float1 }
+            ]));
+
+            let _tex_sphere_id = renderer.register_tex_method("color_sphere".to_string(), DataDeserializer::new(vec![
+                DataEntry{ name: "sph_color".into(), type_: miniquad::UniformType::Float3 }
+            ]));
+
+            let _tex_plane_id = renderer.register_tex_method("color_plane".to_string(), DataDeserializer::new(vec![]));
+
+            let _tex_material_id = renderer.register_tex_method("material_constant".to_string(), DataDeserializer::material());
+
+            let library = renderer.register_primitive_library();
+            let scene = renderer.scene_mut();
+            scene.add_instance(library.instance(Primitive::Torus, &[4.0,0.0,10.0, 0.8, 0.25], [0.9,0.8,0.2]));
+            scene.add_instance(library.instance(Primitive::RoundedBox, &[-4.0,0.0,10.0, 0.7,0.7,0.7, 0.15], [0.3,0.4,0.9]));
+            scene.mark_dirty();
     }
 
-    fn update(&mut self,scene: &mut SimpleScene, backend: &mut B) {
+    fn update(&mut self,_scene: &mut SimpleScene, backend: &mut SwitchableBackend) {
+        if self.switch_backend {
+            backend.switch_next();
+            self.switch_backend = false;
+        }
+        if self.toggle_dynamic_resolution {
+            if let Some(scaled) = backend.backend_mut::<ScaledEstimateBackend>(){
+                let settings = scaled.dynamic_resolution().is_none().then(DynamicResolution::default);
+                scaled.set_dynamic_resolution(settings);
+                println!("Dynamic resolution {}, scale {:.2}", if settings.is_some() { "on" } else { "off" }, scaled.scale());
+            }
+            self.toggle_dynamic_resolution = false;
+        }
         if self.key_map.contains(&KeyCode::W){
             self.position[2] += 0.01;
         }
         if self.key_map.contains(&KeyCode::S){
             self.position[2] -= 0.01;
         }
-        backend.set_position(self.position.clone());
+        backend.set_position(self.position);
+        backend.set_rotation(self.rotation);
+        backend.set_debug_view(self.debug_view);
     }
-    fn key_down_event(&mut self, _ctx: &mut Context, keycode: miniquad::KeyCode, _keymods: miniquad::KeyMods, _repeat: bool) {
+    fn frame_finished(&mut self, stats: &FrameStats) {
+        if !self.print_stats {
+            return;
+        }
+        self.print_stats = false;
+        println!("{:.1} fps over {} frames", stats.fps().unwrap_or(0.0), stats.len());
+        for metric in [FrameMetric::CpuTime, FrameMetric::GpuTime]{
+            if let Some(summary) = stats.summary(metric){
+                println!("{}: min {:.2}ms avg {:.2}ms p95 {:.2}ms max {:.2}ms", metric.name(),
+                    summary.min * 1000.0, summary.average * 1000.0, summary.p95 * 1000.0, summary.max * 1000.0);
+            }
+        }
+    }
+    fn key_down_event(&mut self, _ctx: &mut Context, keycode: miniquad::KeyCode, _keymods: miniquad::KeyMods, repeat: bool) {
+        // B switches between the full size and scaled backend
+        if keycode == KeyCode::B && !repeat {
+            self.switch_backend = true;
+        }
+        // R toggles dynamic resolution of the scaled backend
+        if keycode == KeyCode::R && !repeat {
+            self.toggle_dynamic_resolution = true;
+        }
+        // F prints how long the last frames took
+        if keycode == KeyCode::F && !repeat {
+            self.print_stats = true;
+        }
+        // V cycles through the debug views and back to the shaded scene
+        if keycode == KeyCode::V && !repeat {
+            self.debug_view = match self.debug_view{
+                None => Some(DebugView::ALL[0]),
+                Some(view) => DebugView::ALL.iter().skip_while(|x| **x != view).nth(1).copied(),
+            };
+        }
         self.key_map.insert(keycode);
     }
     fn key_up_event(&mut self, _ctx: &mut Context, keycode: miniquad::KeyCode, _keymods: miniquad::KeyMods) {
@@ -134,22 +197,40 @@ fn main() {
             sample_count: 1,
             ..Default::default()
         },
-        |mut ctx| {
+        |ctx| {
 
             let mut scene = SimpleScene::new();
 
-            scene.add_instance(SimpleSphere::new([-2.0,0.0,7.0], 1.0));
-            scene.add_instance(SimpleSphere::new([2.0,0.0,7.0], 1.0));
-
-            scene.add_instance(SimplePlane::new([0.0,1.0,0.0], -20.0));
+            scene.add_instance(SceneNode::group(CsgOp::SmoothSubtract(0.1), vec![
+                SceneNode::instance(SimpleSphere::new([-2.0,0.0,7.0], 1.0, Material::diffuse([0.0,1.0,1.0]))),
+                SceneNode::instance(SimpleSphere::new([-2.5,0.5,6.3], 0.6, Material::default())),
+            ]));
+            scene.add_instance(SimpleSphere::new([2.0,0.0,7.0], 1.0, Material::glass([0.9,1.0,0.9], 1.5)));
+            scene.add_instance(
+                SceneNode::instance(SimpleSphere::new([0.0;3], 1.0, Material::diffuse([1.0,0.5,0.0])))
+                    .transformed(Transform::scale([0.5,1.5,0.5]).with_rotation([0.0,0.0,1.0], 0.5))
+                    .animated(Animation::Spin{ axis: [0.0,1.0,0.0], speed: 1.0 })
+                    .transformed(Transform::translate([0.0,1.0,9.0]))
+            );
+            scene.add_instance(
+                SceneNode::instance(SimpleSphere::new([0.0;3], 0.3, Material::diffuse([1.0,0.2,0.2])))
+                    .modified(DomainModifier::RepeatLimited{ period: [1.0,0.0,0.0], limit: [3.0,0.0,0.0] })
+                    .transformed(Transform::translate([0.0,-1.5,8.0]))
+            );
+
+            scene.add_instance(SimplePlane::new([0.0,1.0,0.0], -20.0, Material::mirror([0.8,0.8,0.8])));
 
             scene.mark_dirty();
 
-            UserData::owning(Renderer::<_,FullSizeBackend,_>::new(&mut ctx, scene, Logic{
+            Box::new(Renderer::<_,SwitchableBackend,_>::new(ctx, scene, Logic{
                 position: [0.0;3],
                 rotation: [0.0,0.0,0.0,1.0],
+                debug_view: None,
+                switch_backend: false,
+                toggle_dynamic_resolution: false,
+                print_stats: false,
                 key_map: HashSet::new(),
-            }),ctx)
+            }))
         }
     )
 }
@@ -0,0 +1,45 @@
+/// Replaces the shaded image with a view of what the marcher did, for tracking down slow scenes and bad sdfs.
+///
+/// Only the primary rays are shown, and the colors skip tone mapping.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView{
+    /// Number of march steps, from blue for few to red for the step limit.
+    Steps,
+    /// Distance to the hit, white up close fading to black at the maximum distance.
+    Depth,
+    /// Surface normals mapped from `[-1, 1]` to colors.
+    Normals,
+    /// A color per hit instance, picked from where its tex data sits in the rom.
+    InstanceId,
+    /// Share of the bound methods that culled the instance for the ray, from blue for none to red for all.
+    BoundMask,
+    /// Sdf value the march stopped at, green near the hit distance, red further out and blue inside.
+    SdfValue,
+}
+
+impl DebugView{
+    pub const ALL: [DebugView;6] = [
+        DebugView::Steps,
+        DebugView::Depth,
+        DebugView::Normals,
+        DebugView::InstanceId,
+        DebugView::BoundMask,
+        DebugView::SdfValue,
+    ];
+
+    pub fn code(&self) -> i32{
+        match self{
+            DebugView::Steps => 1,
+            DebugView::Depth => 2,
+            DebugView::Normals => 3,
+            DebugView::InstanceId => 4,
+            DebugView::BoundMask => 5,
+            DebugView::SdfValue => 6,
+        }
+    }
+}
+
+/// `debug_view` code of a view, 0 renders normally.
+pub(crate) fn debug_view_code(view: Option<DebugView>) -> i32{
+    view.map(|x| x.code()).unwrap_or(0)
+}
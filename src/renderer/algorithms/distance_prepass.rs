@@ -0,0 +1,111 @@
+use miniquad::{Pipeline, Bindings, RenderPass, Context, Texture, PassAction};
+
+use super::{SceneUniformShader, new_float_render_texture};
+use crate::renderer::camera::FOCAL_LENGTH;
+
+/// Marches every pixel from the camera, the `march_mode` of backends without a prepass.
+pub(super) const MARCH_FULL: i32 = 0;
+/// Every pixel cone marches the tile around it and writes how far its rays can skip.
+const MARCH_CONE: i32 = 1;
+/// Primary rays start at the distance the prepass stored for their tile.
+const MARCH_SEEDED: i32 = 2;
+
+/// Sampler the scene shader reads the prepass distances from, always bound after the scene images.
+const START_DISTANCE_IMAGE: &str = "start_distance_tex";
+
+/// Scene pixels per side of a prepass tile suggested for `set_distance_prepass`.
+pub const DEFAULT_PREPASS_TILE_SIZE: u32 = 8;
+
+struct PrepassTarget{
+    pass: RenderPass,
+    /// Color texture of `pass`, freed with it
+    texture: Texture,
+    size: (u32, u32),
+}
+
+/// Cone marches the scene at one ray per tile and seeds the full resolution march of the tile with the result.
+/// Runs through the scene pipeline of the backend, the pipeline only needs the start distance sampler from `scene_images`.
+pub(super) struct DistancePrepass{
+    tile_size: Option<u32>,
+    target: Option<PrepassTarget>,
+    /// Bound in place of the distances while the prepass itself renders or is disabled
+    placeholder: Texture,
+}
+
+impl DistancePrepass{
+    pub fn new(ctx: &mut Context) -> Self{
+        Self{
+            tile_size: None,
+            target: None,
+            placeholder: Texture::from_rgba8(ctx, 1, 1, &[0;4]),
+        }
+    }
+
+    pub fn tile_size(&self) -> Option<u32>{
+        self.tile_size
+    }
+
+    /// `None` marches every pixel from the camera, the target is rebuilt with the next frame.
+    pub fn set_tile_size(&mut self, tile_size: Option<u32>){
+        assert!(tile_size != Some(0), "Prepass tiles need at least one pixel");
+        self.tile_size = tile_size;
+    }
+
+    /// Scene images followed by the start distances, for the meta of the scene shader.
+    pub fn scene_images(&self, images: &[(String, Texture)]) -> Vec<(String, Texture)>{
+        let mut images = images.to_vec();
+        images.push((START_DISTANCE_IMAGE.to_string(), self.placeholder));
+        images
+    }
+
+    /// Fills in the distances for a scene target of `scene_size` pixels and sets up `bind` and `uniforms` to use them.
+    /// `bind` has to end with the image of `scene_images`.
+    pub fn render(&mut self, ctx: &mut Context, pipeline: &Pipeline, bind: &mut Bindings, uniforms: &mut SceneUniformShader, scene_size: (u32, u32)){
+        let last = bind.images.len() - 1;
+        bind.images[last] = self.placeholder;
+
+        let Some(tile_size) = self.tile_size else {
+            self.delete(ctx);
+            uniforms.march_mode = MARCH_FULL;
+            return;
+        };
+
+        let size = (scene_size.0.div_ceil(tile_size), scene_size.1.div_ceil(tile_size));
+        if self.target.as_ref().map(|target| target.size) != Some(size){
+            self.delete(ctx);
+            let texture = new_float_render_texture(ctx, size.0, size.1);
+            self.target = Some(PrepassTarget{
+                pass: RenderPass::new(ctx, texture, None),
+                texture,
+                size
+            });
+        }
+        let target = self.target.as_ref().unwrap();
+
+        // Rays of a tile leave the camera within half its diagonal of the tile center,
+        // one scene pixel more covers tiles picked on the wrong side of an edge
+        let tile = (2.0 / size.0 as f32, 2.0 * uniforms.fov_y / size.1 as f32);
+        let pixel = 2.0 / scene_size.0 as f32;
+        let jitter = uniforms.jitter;
+        uniforms.jitter = [0.0, 0.0];
+        uniforms.march_mode = MARCH_CONE;
+        uniforms.cone_radius = (0.5 * tile.0.hypot(tile.1) + pixel) / FOCAL_LENGTH;
+
+        ctx.begin_pass(target.pass, PassAction::clear_color(0.0, 0.0, 0.0, 0.0));
+        ctx.apply_pipeline(pipeline);
+        ctx.apply_bindings(bind);
+        ctx.apply_uniforms(uniforms);
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+
+        uniforms.jitter = jitter;
+        uniforms.march_mode = MARCH_SEEDED;
+        bind.images[last] = target.texture;
+    }
+
+    pub fn delete(&mut self, ctx: &mut Context){
+        if let Some(target) = self.target.take(){
+            target.pass.delete(ctx);
+        }
+    }
+}
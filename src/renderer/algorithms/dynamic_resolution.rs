@@ -0,0 +1,103 @@
+/// Settings for `ScaledEstimateBackend` picking its own scale to keep frames within a time budget.
+///
+/// Frame times are measured between consecutive renders, so with vsync on the budget should be above the refresh interval
+/// or the resolution never goes back up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DynamicResolution{
+    /// Seconds a frame may take.
+    pub target_frame_time: f32,
+    /// Finest scale used, in screen pixels per scene pixel.
+    pub min_scale: f32,
+    /// Coarsest scale used.
+    pub max_scale: f32,
+    /// Factor the scale is multiplied or divided by per change.
+    pub step: f32,
+    /// Share of the budget that has to be left over before the resolution is raised, so it doesn't flip back and forth.
+    pub headroom: f32,
+    /// Frames the averaged frame time has to stay over budget, or under it with headroom, before the scale changes.
+    pub settle_frames: u32,
+}
+
+impl Default for DynamicResolution{
+    fn default() -> Self {
+        Self{
+            target_frame_time: 1.0 / 60.0,
+            min_scale: 1.0,
+            max_scale: 4.0,
+            step: 1.25,
+            headroom: 0.2,
+            settle_frames: 10,
+        }
+    }
+}
+
+/// Averages frame times and decides when `DynamicResolution` changes the scale.
+#[derive(Clone, Debug)]
+pub struct ScaleController{
+    settings: DynamicResolution,
+    average: Option<f32>,
+    frames_over: u32,
+    frames_under: u32,
+}
+
+impl ScaleController{
+    pub fn new(settings: DynamicResolution) -> Self{
+        assert!(settings.min_scale > 0.0 && settings.min_scale <= settings.max_scale, "Dynamic resolution needs 0 < min_scale <= max_scale");
+        assert!(settings.step > 1.0, "Dynamic resolution needs a step above 1");
+        Self{
+            settings,
+            average: None,
+            frames_over: 0,
+            frames_under: 0,
+        }
+    }
+
+    pub fn settings(&self) -> DynamicResolution{
+        self.settings
+    }
+
+    /// Smoothed frame time since the scale last changed.
+    pub fn average_frame_time(&self) -> Option<f32>{
+        self.average
+    }
+
+    /// Takes the time the last frame took and the current scale, returns the scale to render the next frame with.
+    pub fn update(&mut self, frame_time: f32, scale: f32) -> f32{
+        let settings = self.settings;
+        // Starting from the budget keeps a single slow first frame, like the one rebuilding the targets, from counting for much
+        let average = self.average.unwrap_or(settings.target_frame_time);
+        let average = average + (frame_time - average) * 0.25;
+        self.average = Some(average);
+
+        if average > settings.target_frame_time{
+            self.frames_over += 1;
+            self.frames_under = 0;
+        }
+        else if average < settings.target_frame_time * (1.0 - settings.headroom){
+            self.frames_under += 1;
+            self.frames_over = 0;
+        }
+        else{
+            self.frames_over = 0;
+            self.frames_under = 0;
+        }
+
+        let new_scale = if self.frames_over >= settings.settle_frames{
+            scale * settings.step
+        }
+        else if self.frames_under >= settings.settle_frames{
+            scale / settings.step
+        }
+        else{
+            scale
+        }.clamp(settings.min_scale, settings.max_scale);
+
+        if new_scale != scale{
+            // Frames at the old scale say nothing about the new one
+            self.average = None;
+            self.frames_over = 0;
+            self.frames_under = 0;
+        }
+        new_scale
+    }
+}
@@ -1,10 +1,8 @@
-use miniquad::{Pipeline, BufferLayout, VertexAttribute, UniformDesc, UniformType, UniformBlockLayout, Shader, Bindings, Buffer, BufferType, ShaderMeta, VertexFormat, PassAction};
+use miniquad::{Pipeline, BufferLayout, VertexAttribute, Shader, Bindings, Buffer, BufferType, VertexFormat, PassAction, Texture, RenderPass, Context, ShaderMeta, UniformBlockLayout, UniformDesc, UniformType, PipelineParams, BlendState, Equation, BlendFactor, BlendValue};
 
-use crate::renderer::MAX_ROM_SIZE;
+use super::{RayMarcherBackend, VERTS, INDICES, SceneUniformShader, scene_shader_meta, scene_pipeline_params, new_float_render_texture, new_depth_render_texture, ToneMapping, ToneMapUniforms, DebugView, debug_view_code, TEXTURE_VERTEX_SHADER, present_fragment_shader, DistancePrepass};
 
-use super::{RayMarcherBackend, VERTS, INDICES, SceneUniformShader};
-
-const VERTEX_SHADER: &'static str = 
+const VERTEX_SHADER: &str = 
 "#version 330
 in vec2 pos;
 
@@ -17,7 +15,7 @@ void main(){
     f_pos = vec2(pos.x,pos.y * fov_y);
 }";
 
-const FRAGMENT_SHADER: &'static str = 
+const FRAGMENT_SHADER: &str = 
 "#version 330
 
 in vec2 f_pos;
@@ -34,10 +32,76 @@ void main(){
 ";
 
 
+const ACCUMULATE_FRAGMENT_SHADER: &str = 
+"#version 330
+
+in vec2 f_uv;
+
+out vec4 f_color;
+
+uniform sampler2D tex;
+uniform float weight;
+
+void main(){
+    f_color = vec4(texture(tex, f_uv).rgb, weight);
+}
+";
+
+#[repr(C)]
+struct AccumulateUniforms{
+    weight: f32
+}
+
+/// Float targets the jittered samples are rendered to and averaged in, also used to tone map single samples.
+struct AccumulationTargets{
+    sample_pass: RenderPass,
+    sample_bind: Bindings,
+    accumulate_pass: RenderPass,
+    /// Averaged colors and the depth of the latest sample
+    present_bind: Bindings,
+}
+
+impl AccumulationTargets{
+    fn new(ctx: &mut Context, width: u32, height: u32, vertex_buffer: Buffer, index_buffer: Buffer) -> Self{
+        let sample = new_float_render_texture(ctx, width, height);
+        let depth = new_depth_render_texture(ctx, width, height);
+        let accumulate = new_float_render_texture(ctx, width, height);
+        Self{
+            sample_pass: RenderPass::new(ctx, sample, depth),
+            sample_bind: Bindings{
+                vertex_buffers: vec![vertex_buffer],
+                index_buffer,
+                images: vec![sample]
+            },
+            accumulate_pass: RenderPass::new(ctx, accumulate, None),
+            present_bind: Bindings{
+                vertex_buffers: vec![vertex_buffer],
+                index_buffer,
+                images: vec![accumulate, depth]
+            },
+        }
+    }
+
+    fn delete(&self, ctx: &mut Context){
+        self.sample_pass.delete(ctx);
+        self.accumulate_pass.delete(ctx);
+    }
+}
+
 pub struct FullSizeBackend{
     scene_pipeline: Pipeline,
     scene_bind: Bindings,
+    scene_images: Vec<(String, Texture)>,
     uniforms: SceneUniformShader,
+    size: (f32, f32),
+    distance_prepass: DistancePrepass,
+
+    accumulate_pipeline: Pipeline,
+    present_pipeline: Pipeline,
+    accumulation_targets: Option<AccumulationTargets>,
+    max_samples: Option<u32>,
+    samples: u32,
+    tone_mapping: Option<ToneMapping>,
 }
 
 impl RayMarcherBackend for FullSizeBackend {
@@ -46,36 +110,70 @@ impl RayMarcherBackend for FullSizeBackend {
         let vertex_buffer = Buffer::immutable(ctx, BufferType::VertexBuffer, &VERTS);
         let index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, &INDICES);
 
+        let distance_prepass = DistancePrepass::new(ctx);
+        let scene_images = distance_prepass.scene_images(&[]);
+
         let scene_bind = Bindings{
-            vertex_buffers: vec![vertex_buffer.clone()],
-            index_buffer: index_buffer.clone(),
-            images: vec![]
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: scene_images.iter().map(|(_,texture)| *texture).collect()
         };
 
         let (w,h) = ctx.screen_size();
         let fov_y = h / w;
 
-        let scene_shader = Shader::new(ctx, VERTEX_SHADER, &FRAGMENT_SHADER,ShaderMeta{
-            images: vec![],
+        let scene_shader = Shader::new(ctx, VERTEX_SHADER, FRAGMENT_SHADER,scene_shader_meta(&scene_images)).unwrap_or_else(|e| panic!("Failed to compile scene shader: {}",e));
+
+        let scene_pipeline = Pipeline::with_params(
+            ctx, 
+            &[BufferLayout::default()], 
+            &[
+                VertexAttribute::new("pos", VertexFormat::Float2)
+                
+            ],
+            scene_shader,
+            scene_pipeline_params());
+
+        let accumulate_shader = Shader::new(ctx, TEXTURE_VERTEX_SHADER, ACCUMULATE_FRAGMENT_SHADER, ShaderMeta{
+            images: vec!["tex".to_string()],
             uniforms: UniformBlockLayout{
                 uniforms: vec![
-                    UniformDesc::new("fov_y",UniformType::Float1),
-                    UniformDesc::new("elapsed_time", UniformType::Float1),
-                    UniformDesc::new("position", UniformType::Float3),
-                    UniformDesc::new("rotation", UniformType::Float4),
-                    UniformDesc::new("scene_rom", UniformType::Int1).array(MAX_ROM_SIZE)
+                    UniformDesc::new("weight", UniformType::Float1)
                 ],
             }
-        }).unwrap_or_else(|e| panic!("Failed to compile scene shader: {}",e));
+        }).unwrap_or_else(|e| panic!("Failed to compile accumulate shader: {}",e));
 
-        let scene_pipeline = Pipeline::new(
-            ctx, 
-            &[BufferLayout::default()], 
+        // Blends every new sample in by its weight, a weight of 1 / n keeps the running average
+        let accumulate_pipeline = Pipeline::with_params(
+            ctx,
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("pos", VertexFormat::Float2)
+            ],
+            accumulate_shader,
+            PipelineParams{
+                color_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Value(BlendValue::SourceAlpha),
+                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha))),
+                ..Default::default()
+            });
+
+        let present_shader = Shader::new(ctx, TEXTURE_VERTEX_SHADER, &present_fragment_shader(), ShaderMeta{
+            images: vec!["tex".to_string(), "depth_tex".to_string()],
+            uniforms: UniformBlockLayout{
+                uniforms: ToneMapUniforms::uniform_descs(),
+            }
+        }).unwrap_or_else(|e| panic!("Failed to compile present shader: {}",e));
+
+        let present_pipeline = Pipeline::with_params(
+            ctx,
+            &[BufferLayout::default()],
             &[
                 VertexAttribute::new("pos", VertexFormat::Float2)
-                
             ],
-        scene_shader);
+            present_shader,
+            scene_pipeline_params());
 
         let mut uniforms = SceneUniformShader::new();
 
@@ -83,63 +181,190 @@ impl RayMarcherBackend for FullSizeBackend {
         Self{
             scene_pipeline,
             scene_bind,
-            uniforms
+            scene_images: Vec::new(),
+            uniforms,
+            size: (w, h),
+            distance_prepass,
+
+            accumulate_pipeline,
+            present_pipeline,
+            accumulation_targets: None,
+            max_samples: None,
+            samples: 0,
+            tone_mapping: None,
         }
     }
 
     fn resize(&mut self, ctx: &mut miniquad::Context, width: f32, height: f32) {
         self.uniforms.fov_y = height / width;
+        self.size = (width, height);
+        if let Some(targets) = self.accumulation_targets.take(){
+            targets.delete(ctx);
+        }
+        self.reset_accumulation();
     }
 
     fn render(&mut self, ctx: &mut miniquad::Context) {
+        let scene_size = (self.size.0 as u32, self.size.1 as u32);
+        if self.max_samples.is_none() && self.tone_mapping.is_none(){
+            self.distance_prepass.render(ctx, &self.scene_pipeline, &mut self.scene_bind, &mut self.uniforms, scene_size);
+            ctx.begin_default_pass(PassAction::clear_color(1.0, 1.0, 1.0, 1.0));
+            ctx.apply_pipeline(&self.scene_pipeline);
+            ctx.apply_bindings(&self.scene_bind);
+            ctx.apply_uniforms(&self.uniforms);
+            ctx.draw(0, 6, 1);
+            ctx.end_render_pass();
+            return;
+        }
+
+        // Without accumulation every frame replaces the single sample
+        if self.max_samples.is_none(){
+            self.samples = 0;
+        }
+        let max_samples = self.max_samples.unwrap_or(1);
+
+        if self.accumulation_targets.is_none(){
+            let (width, height) = self.size;
+            let vertex_buffer = self.scene_bind.vertex_buffers[0];
+            let index_buffer = self.scene_bind.index_buffer;
+            self.accumulation_targets = Some(AccumulationTargets::new(ctx, width as u32, height as u32, vertex_buffer, index_buffer));
+        }
+        let targets = self.accumulation_targets.as_ref().unwrap();
+
+        // Once enough samples are in, the finished image is only presented again
+        if self.samples < max_samples {
+            let pixel_size = 2.0 / self.size.0;
+            self.uniforms.jitter = [
+                sample_offset(self.samples, 2) * pixel_size,
+                sample_offset(self.samples, 3) * pixel_size
+            ];
+            self.distance_prepass.render(ctx, &self.scene_pipeline, &mut self.scene_bind, &mut self.uniforms, scene_size);
+
+            ctx.begin_pass(targets.sample_pass, PassAction::clear_color(0.0, 0.0, 0.0, 1.0));
+            ctx.apply_pipeline(&self.scene_pipeline);
+            ctx.apply_bindings(&self.scene_bind);
+            ctx.apply_uniforms(&self.uniforms);
+            ctx.draw(0, 6, 1);
+            ctx.end_render_pass();
+
+            ctx.begin_pass(targets.accumulate_pass, PassAction::Nothing);
+            ctx.apply_pipeline(&self.accumulate_pipeline);
+            ctx.apply_bindings(&targets.sample_bind);
+            ctx.apply_uniforms(&AccumulateUniforms{
+                weight: 1.0 / (self.samples + 1) as f32
+            });
+            ctx.draw(0, 6, 1);
+            ctx.end_render_pass();
+
+            self.samples += 1;
+        }
+
         ctx.begin_default_pass(PassAction::clear_color(1.0, 1.0, 1.0, 1.0));
-        ctx.apply_pipeline(&self.scene_pipeline);
-        ctx.apply_bindings(&self.scene_bind);
-        ctx.apply_uniforms(&self.uniforms);
+        ctx.apply_pipeline(&self.present_pipeline);
+        ctx.apply_bindings(&targets.present_bind);
+        // Debug colors are shown as they are
+        ctx.apply_uniforms(&ToneMapUniforms::new(self.tone_mapping.filter(|_| self.uniforms.debug_view == 0)));
         ctx.draw(0, 6, 1);
         ctx.end_render_pass();
-
-        ctx.commit_frame();
     }
 
     fn set_elapsed(&mut self, time: f32) {
         self.uniforms.elapsed_time = time;
     }
 
+    fn set_frame_index(&mut self, frame: u32) {
+        self.uniforms.frame_index = frame as i32;
+    }
+
     fn get_scene_rom(&mut self) -> &mut [u32] {
         &mut self.uniforms.scene_rom[..]
     }
 
     fn recreate_scene_shader(&mut self, ctx: &mut miniquad::Context, fragment: String) {
-        let scene_shader = Shader::new(ctx, VERTEX_SHADER, &fragment,ShaderMeta{
-            images: vec![],
-            uniforms: UniformBlockLayout{
-                uniforms: vec![
-                    UniformDesc::new("fov_y",UniformType::Float1),
-                    UniformDesc::new("elapsed_time", UniformType::Float1),
-                    UniformDesc::new("position", UniformType::Float3),
-                    UniformDesc::new("rotation", UniformType::Float4),
-                    UniformDesc::new("scene_rom", UniformType::Int1).array(MAX_ROM_SIZE)
-                ],
-            }
-        }).unwrap_or_else(|e| panic!("Failed to compile scene shader: {}",e));
+        let scene_shader = Shader::new(ctx, VERTEX_SHADER, &fragment,scene_shader_meta(&self.distance_prepass.scene_images(&self.scene_images))).unwrap_or_else(|e| panic!("Failed to compile scene shader: {}",e));
 
-        let scene_pipeline = Pipeline::new(
+        let scene_pipeline = Pipeline::with_params(
             ctx, 
             &[BufferLayout::default()], 
             &[
                 VertexAttribute::new("pos", VertexFormat::Float2)
             ],
-        scene_shader);
+            scene_shader,
+            scene_pipeline_params());
 
         self.scene_pipeline = scene_pipeline;
     }
 
     fn set_position(&mut self, position: [f32;3]) {
+        if self.uniforms.position != position {
+            self.reset_accumulation();
+        }
         self.uniforms.position = position;
     }
 
     fn set_rotation(&mut self, rotation: [f32;4]) {
+        if self.uniforms.rotation != rotation {
+            self.reset_accumulation();
+        }
         self.uniforms.rotation = rotation;
     }
-}
\ No newline at end of file
+
+    fn set_scene_images(&mut self, images: Vec<(String, Texture)>) {
+        self.scene_bind.images = self.distance_prepass.scene_images(&images).iter().map(|(_,texture)| *texture).collect();
+        self.scene_images = images;
+    }
+
+    fn reset_accumulation(&mut self) {
+        self.samples = 0;
+        self.uniforms.jitter = [0.0,0.0];
+    }
+
+    fn set_tone_mapping(&mut self, tone_mapping: Option<ToneMapping>) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    fn set_debug_view(&mut self, view: Option<DebugView>) {
+        let code = debug_view_code(view);
+        if self.uniforms.debug_view != code {
+            self.reset_accumulation();
+        }
+        self.uniforms.debug_view = code;
+    }
+}
+
+impl FullSizeBackend{
+    /// Renders up to `max_samples` jittered frames while the camera and scene stay the same and shows their average,
+    /// `None` renders a single sample through the pixel centers every frame.
+    pub fn set_accumulation(&mut self, max_samples: Option<u32>){
+        self.max_samples = max_samples;
+        self.reset_accumulation();
+    }
+
+    /// `Some` cone marches tiles of `tile_size` pixels before every frame and starts the rays of each tile
+    /// where the cone first came close to a surface, `None` marches every ray from the camera.
+    pub fn set_distance_prepass(&mut self, tile_size: Option<u32>){
+        self.distance_prepass.set_tile_size(tile_size);
+    }
+
+    pub fn distance_prepass(&self) -> Option<u32>{
+        self.distance_prepass.tile_size()
+    }
+
+    /// Number of samples in the image currently shown.
+    pub fn accumulated_samples(&self) -> u32{
+        self.samples
+    }
+}
+
+/// Sub-pixel offset of a sample in `[-0.5, 0.5)`, from a halton sequence so any number of samples covers the pixel evenly.
+/// The first sample goes through the pixel center.
+fn sample_offset(mut index: u32, base: u32) -> f32{
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    (result + 0.5).fract() - 0.5
+}
@@ -0,0 +1,449 @@
+use miniquad::{Pipeline, Bindings, RenderPass, Context, BufferType, Buffer, Shader, UniformBlockLayout, UniformDesc, UniformType, BufferLayout, VertexAttribute, VertexFormat, Texture, ShaderMeta, PassAction};
+
+use super::{SceneUniformShader, RayMarcherBackend, VERTS, INDICES, scene_shader_meta, scene_pipeline_params, new_float_render_texture, new_depth_render_texture, ToneMapping, ToneMapUniforms, DebugView, debug_view_code, TEXTURE_VERTEX_SHADER, present_fragment_shader};
+
+const FRAGMENT_SHADER: &str =
+"#version 330
+
+in vec2 f_pos;
+
+out vec4 f_color;
+
+void main(){
+    f_color = vec4(0,0,0,1);
+}
+";
+
+/// Which half of the pixels is marched in a frame, the other half is marched the frame after.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterleavePattern{
+    /// Alternating pixels in every row, missing pixels are rebuilt from all four neighbors.
+    Checkerboard,
+    /// Every other row, missing rows are rebuilt from the rows above and below.
+    Interlaced,
+}
+
+impl InterleavePattern{
+    /// Distance in screen pixels between pixels marched in the same draw.
+    fn stride(&self) -> (u32, u32){
+        match self{
+            InterleavePattern::Checkerboard => (2, 2),
+            InterleavePattern::Interlaced => (1, 2),
+        }
+    }
+
+    /// Pixels marched by each draw, the checkerboard needs one draw for the even and one for the odd rows.
+    fn lattice_size(&self, size: (f32, f32)) -> (u32, u32){
+        let (stride_x, stride_y) = self.stride();
+        ((size.0 as u32).div_ceil(stride_x).max(1), (size.1 as u32).div_ceil(stride_y).max(1))
+    }
+
+    /// The lattices are stacked on top of each other in the scene target.
+    fn draws(&self) -> u32{
+        match self{
+            InterleavePattern::Checkerboard => 2,
+            InterleavePattern::Interlaced => 1,
+        }
+    }
+
+    /// Screen pixel offset of the first pixel a draw marches, `parity` alternates every frame.
+    fn draw_offset(&self, draw: u32, parity: u32) -> (u32, u32){
+        match self{
+            InterleavePattern::Checkerboard => ((parity + draw) % 2, draw),
+            InterleavePattern::Interlaced => (0, parity),
+        }
+    }
+}
+
+/// Maps the lattice a draw marches onto the screen, the offset of the draw comes in through `jitter`.
+fn scene_vertex_shader(pattern: InterleavePattern, size: (f32, f32)) -> String{
+    let (lattice_width, lattice_height) = pattern.lattice_size(size);
+    let (stride_x, stride_y) = pattern.stride();
+    format!("#version 330
+in vec2 pos;
+
+out vec2 f_pos;
+
+void main(){{
+    gl_Position = vec4(pos,0.1,1.0);
+    vec2 stride = vec2({stride_x:?}, {stride_y:?});
+    // Centers of the marched pixels in screen pixels, as if the draw had no offset
+    vec2 pixel = (pos * 0.5 + 0.5) * vec2({lattice_width:?}, {lattice_height:?}) * stride - (stride - 1.0) * 0.5;
+    f_pos = (pixel * 2.0 - vec2({width:?}, {height:?})) / {width:?};
+}}
+",
+        stride_x = stride_x as f32,
+        stride_y = stride_y as f32,
+        lattice_width = lattice_width as f32,
+        lattice_height = lattice_height as f32,
+        width = size.0,
+        height = size.1)
+}
+
+/// Copies the marched pixels to the full size history target and rebuilds the rest,
+/// from the neighbors marched this frame and the pixel marched the frame before.
+/// The previous pixel is clamped to the range of its neighbors, so moving edges don't leave trails.
+fn reconstruct_fragment_shader(pattern: InterleavePattern, size: (f32, f32)) -> String{
+    let (_, lattice_height) = pattern.lattice_size(size);
+    let (marched, texel, rebuild) = match pattern{
+        InterleavePattern::Checkerboard => (
+            "((p.x + p.y) & 1) == parity",
+            format!("ivec2(p.x / 2, p.y / 2 + (p.y & 1) * {})", lattice_height),
+            "vec4 l = marched_color(p + ivec2(-1, 0));
+    vec4 r = marched_color(p + ivec2(1, 0));
+    vec4 d = marched_color(p + ivec2(0, -1));
+    vec4 u = marched_color(p + ivec2(0, 1));
+    // Interpolates along the direction the colors change the least, so edges stay sharp
+    spatial = length(l.rgb - r.rgb) < length(u.rgb - d.rgb) ? (l + r) * 0.5 : (u + d) * 0.5;
+    low = min(min(l.rgb, r.rgb), min(u.rgb, d.rgb));
+    high = max(max(l.rgb, r.rgb), max(u.rgb, d.rgb));
+    depth = min(min(marched_depth(p + ivec2(-1, 0)), marched_depth(p + ivec2(1, 0))), min(marched_depth(p + ivec2(0, -1)), marched_depth(p + ivec2(0, 1))));",
+        ),
+        InterleavePattern::Interlaced => (
+            "(p.y & 1) == parity",
+            "ivec2(p.x, p.y / 2)".to_string(),
+            "vec4 d = marched_color(p + ivec2(0, -1));
+    vec4 u = marched_color(p + ivec2(0, 1));
+    spatial = (u + d) * 0.5;
+    low = min(u.rgb, d.rgb);
+    high = max(u.rgb, d.rgb);
+    depth = min(marched_depth(p + ivec2(0, -1)), marched_depth(p + ivec2(0, 1)));",
+        ),
+    };
+
+    format!("#version 330
+
+out vec4 f_color;
+
+uniform sampler2D tex;
+uniform sampler2D depth_tex;
+uniform sampler2D history_tex;
+uniform int parity;
+uniform float history_weight;
+
+bool is_marched(ivec2 p){{
+    return {marched};
+}}
+
+// Texel of the scene target holding the pixel, pixels that weren't marched share it with a marched neighbor
+ivec2 marched_texel(ivec2 p){{
+    p = clamp(p, ivec2(0), ivec2({width}, {height}) - 1);
+    return {texel};
+}}
+
+vec4 marched_color(ivec2 p){{
+    return texelFetch(tex, marched_texel(p), 0);
+}}
+
+float marched_depth(ivec2 p){{
+    return texelFetch(depth_tex, marched_texel(p), 0).r;
+}}
+
+void main(){{
+    ivec2 p = ivec2(gl_FragCoord.xy);
+    if (is_marched(p)){{
+        f_color = marched_color(p);
+        gl_FragDepth = marched_depth(p);
+        return;
+    }}
+
+    vec4 spatial;
+    vec3 low;
+    vec3 high;
+    float depth;
+    {rebuild}
+
+    vec3 history = clamp(texelFetch(history_tex, p, 0).rgb, low, high);
+    f_color = vec4(mix(spatial.rgb, history, history_weight), 1.0);
+    gl_FragDepth = depth;
+}}
+",
+        marched = marched,
+        texel = texel,
+        rebuild = rebuild,
+        width = size.0 as u32,
+        height = size.1 as u32)
+}
+
+#[repr(C)]
+struct ReconstructUniforms{
+    parity: i32,
+    history_weight: f32
+}
+
+/// The scene target the lattices are marched into, and two full size history targets taking turns
+/// being rebuilt and being the previous frame.
+struct InterleavedTargets{
+    scene_pass: RenderPass,
+    history_passes: [RenderPass;2],
+    /// Reads the scene target and the history target not being written
+    reconstruct_binds: [Bindings;2],
+    present_binds: [Bindings;2],
+}
+
+impl InterleavedTargets{
+    fn new(ctx: &mut Context, pattern: InterleavePattern, size: (f32, f32), vertex_buffer: Buffer, index_buffer: Buffer) -> Self{
+        let (lattice_width, lattice_height) = pattern.lattice_size(size);
+        let scene_color = new_float_render_texture(ctx, lattice_width, lattice_height * pattern.draws());
+        let scene_depth = new_depth_render_texture(ctx, lattice_width, lattice_height * pattern.draws());
+
+        let (width, height) = (size.0.max(1.0) as u32, size.1.max(1.0) as u32);
+        let history = [0, 1].map(|_| (new_float_render_texture(ctx, width, height), new_depth_render_texture(ctx, width, height)));
+
+        let bind = |images: Vec<Texture>| Bindings{
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images
+        };
+
+        Self{
+            scene_pass: RenderPass::new(ctx, scene_color, scene_depth),
+            history_passes: history.map(|(color, depth)| RenderPass::new(ctx, color, depth)),
+            reconstruct_binds: [0, 1].map(|i| bind(vec![scene_color, scene_depth, history[1 - i].0])),
+            present_binds: history.map(|(color, depth)| bind(vec![color, depth])),
+        }
+    }
+
+    fn delete(&self, ctx: &mut Context){
+        self.scene_pass.delete(ctx);
+        for pass in &self.history_passes{
+            pass.delete(ctx);
+        }
+    }
+}
+
+/// Marches half of the pixels every frame and rebuilds the other half, alternating between the halves.
+/// A still image converges to a fully marched one after two frames.
+pub struct InterleavedBackend{
+    scene_pipeline: Pipeline,
+    scene_bind: Bindings,
+    scene_images: Vec<(String, Texture)>,
+    /// Fragment shader of the scene, kept to rebuild the scene pipeline when the lattice changes
+    scene_fragment: String,
+    uniforms: SceneUniformShader,
+    size: (f32, f32),
+    pattern: InterleavePattern,
+    /// Pattern the targets and pipelines were built for, they are rebuilt before rendering when it changed
+    built_pattern: InterleavePattern,
+
+    targets: InterleavedTargets,
+    reconstruct_pipeline: Pipeline,
+    present_pipeline: Pipeline,
+    /// History target holding the latest frame
+    current: usize,
+    parity: u32,
+    /// The previous frame is only used once it shows the same scene
+    history_valid: bool,
+    tone_mapping: Option<ToneMapping>,
+}
+
+impl RayMarcherBackend for InterleavedBackend{
+
+    fn new(ctx: &mut Context) -> Self {
+        Self::with_pattern(ctx, InterleavePattern::Checkerboard)
+    }
+
+    fn resize(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.uniforms.fov_y = height / width;
+        self.size = (width, height);
+        self.rebuild(ctx);
+    }
+
+    fn render(&mut self, ctx: &mut Context) {
+        if self.built_pattern != self.pattern {
+            self.rebuild(ctx);
+        }
+
+        let (lattice_width, lattice_height) = self.pattern.lattice_size(self.size);
+        let pixel_size = 2.0 / self.size.0;
+
+        ctx.begin_pass(self.targets.scene_pass, PassAction::clear_color(0.0, 0.0, 0.0, 1.0));
+        ctx.apply_pipeline(&self.scene_pipeline);
+        ctx.apply_bindings(&self.scene_bind);
+        for draw in 0..self.pattern.draws(){
+            let (offset_x, offset_y) = self.pattern.draw_offset(draw, self.parity);
+            self.uniforms.jitter = [offset_x as f32 * pixel_size, offset_y as f32 * pixel_size];
+            ctx.apply_viewport(0, (draw * lattice_height) as i32, lattice_width as i32, lattice_height as i32);
+            ctx.apply_uniforms(&self.uniforms);
+            ctx.draw(0, 6, 1);
+        }
+        ctx.end_render_pass();
+
+        let next = 1 - self.current;
+        ctx.begin_pass(self.targets.history_passes[next], PassAction::clear_color(0.0, 0.0, 0.0, 1.0));
+        ctx.apply_pipeline(&self.reconstruct_pipeline);
+        ctx.apply_bindings(&self.targets.reconstruct_binds[next]);
+        ctx.apply_uniforms(&ReconstructUniforms{
+            parity: self.parity as i32,
+            history_weight: if self.history_valid { 1.0 } else { 0.0 }
+        });
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+
+        ctx.begin_default_pass(PassAction::clear_color(1.0, 1.0, 1.0, 1.0));
+        ctx.apply_pipeline(&self.present_pipeline);
+        ctx.apply_bindings(&self.targets.present_binds[next]);
+        // Debug colors are shown as they are
+        ctx.apply_uniforms(&ToneMapUniforms::new(self.tone_mapping.filter(|_| self.uniforms.debug_view == 0)));
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+
+        self.current = next;
+        self.parity = 1 - self.parity;
+        self.history_valid = true;
+    }
+
+    fn set_elapsed(&mut self, time: f32) {
+        self.uniforms.elapsed_time = time;
+    }
+
+    fn set_frame_index(&mut self, frame: u32) {
+        self.uniforms.frame_index = frame as i32;
+    }
+
+    fn set_position(&mut self, position: [f32;3]) {
+        self.uniforms.position = position;
+    }
+
+    fn set_rotation(&mut self, rotation: [f32;4]) {
+        self.uniforms.rotation = rotation;
+    }
+
+    fn get_scene_rom(&mut self) -> &mut [u32] {
+        &mut self.uniforms.scene_rom[..]
+    }
+
+    fn recreate_scene_shader(&mut self, ctx: &mut Context, fragment: String) {
+        self.scene_pipeline = Self::get_scene_pipeline(ctx, self.pattern, self.size, &fragment, &self.scene_images);
+        self.scene_fragment = fragment;
+        self.reset_accumulation();
+    }
+
+    fn set_scene_images(&mut self, images: Vec<(String, Texture)>) {
+        self.scene_bind.images = images.iter().map(|(_,texture)| *texture).collect();
+        self.scene_images = images;
+    }
+
+    fn reset_accumulation(&mut self) {
+        self.history_valid = false;
+    }
+
+    fn set_tone_mapping(&mut self, tone_mapping: Option<ToneMapping>) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    fn set_debug_view(&mut self, view: Option<DebugView>) {
+        let code = debug_view_code(view);
+        if self.uniforms.debug_view != code {
+            self.reset_accumulation();
+        }
+        self.uniforms.debug_view = code;
+    }
+}
+
+impl InterleavedBackend{
+    pub fn with_pattern(ctx: &mut Context, pattern: InterleavePattern) -> Self{
+        let size = ctx.screen_size();
+        let vertex_buffer = Buffer::immutable(ctx, BufferType::VertexBuffer, &VERTS);
+        let index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, &INDICES);
+
+        let scene_bind = Bindings{
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: vec![]
+        };
+
+        let present_shader = Shader::new(ctx, TEXTURE_VERTEX_SHADER, &present_fragment_shader(), ShaderMeta{
+            images: vec!["tex".to_string(), "depth_tex".to_string()],
+            uniforms: UniformBlockLayout{
+                uniforms: ToneMapUniforms::uniform_descs(),
+            }
+        }).unwrap_or_else(|e| panic!("Failed to compile present shader: {}",e));
+
+        let present_pipeline = Pipeline::with_params(
+            ctx,
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("pos", VertexFormat::Float2)
+            ],
+            present_shader,
+            scene_pipeline_params());
+
+        let mut uniforms = SceneUniformShader::new();
+        uniforms.fov_y = size.1 / size.0;
+
+        Self{
+            scene_pipeline: Self::get_scene_pipeline(ctx, pattern, size, FRAGMENT_SHADER, &[]),
+            scene_bind,
+            scene_images: Vec::new(),
+            scene_fragment: FRAGMENT_SHADER.to_string(),
+            uniforms,
+            size,
+            pattern,
+            built_pattern: pattern,
+
+            targets: InterleavedTargets::new(ctx, pattern, size, vertex_buffer, index_buffer),
+            reconstruct_pipeline: Self::get_reconstruct_pipeline(ctx, pattern, size),
+            present_pipeline,
+            current: 0,
+            parity: 0,
+            history_valid: false,
+            tone_mapping: None,
+        }
+    }
+
+    pub fn pattern(&self) -> InterleavePattern{
+        self.pattern
+    }
+
+    /// Switches the pixels marched every frame, the targets and pipelines are rebuilt with the next frame.
+    pub fn set_pattern(&mut self, pattern: InterleavePattern){
+        self.pattern = pattern;
+    }
+
+    fn rebuild(&mut self, ctx: &mut Context){
+        self.built_pattern = self.pattern;
+        self.targets.delete(ctx);
+        let vertex_buffer = self.scene_bind.vertex_buffers[0];
+        let index_buffer = self.scene_bind.index_buffer;
+        self.targets = InterleavedTargets::new(ctx, self.pattern, self.size, vertex_buffer, index_buffer);
+        self.reconstruct_pipeline = Self::get_reconstruct_pipeline(ctx, self.pattern, self.size);
+        self.scene_pipeline = Self::get_scene_pipeline(ctx, self.pattern, self.size, &self.scene_fragment, &self.scene_images);
+        self.reset_accumulation();
+    }
+
+    fn get_scene_pipeline(ctx: &mut Context, pattern: InterleavePattern, size: (f32, f32), fragment: &str, images: &[(String, Texture)]) -> Pipeline{
+        let vertex = scene_vertex_shader(pattern, size);
+        let scene_shader = Shader::new(ctx, &vertex, fragment, scene_shader_meta(images)).unwrap_or_else(|e| panic!("Failed to compile scene shader: {}",e));
+
+        Pipeline::with_params(
+            ctx,
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("pos", VertexFormat::Float2)
+            ],
+            scene_shader,
+            scene_pipeline_params())
+    }
+
+    fn get_reconstruct_pipeline(ctx: &mut Context, pattern: InterleavePattern, size: (f32, f32)) -> Pipeline{
+        let reconstruct_shader = Shader::new(ctx, TEXTURE_VERTEX_SHADER, &reconstruct_fragment_shader(pattern, size), ShaderMeta{
+            images: vec!["tex".to_string(), "depth_tex".to_string(), "history_tex".to_string()],
+            uniforms: UniformBlockLayout{
+                uniforms: vec![
+                    UniformDesc::new("parity", UniformType::Int1),
+                    UniformDesc::new("history_weight", UniformType::Float1),
+                ],
+            }
+        }).unwrap_or_else(|e| panic!("Failed to compile reconstruct shader: {}",e));
+
+        Pipeline::with_params(
+            ctx,
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("pos", VertexFormat::Float2)
+            ],
+            reconstruct_shader,
+            scene_pipeline_params())
+    }
+}
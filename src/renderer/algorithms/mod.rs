@@ -1,29 +1,85 @@
-use miniquad::Context;
+use miniquad::{Context, Texture, ShaderMeta, UniformBlockLayout, UniformDesc, UniformType, TextureParams, FilterMode, gl, PipelineParams, TextureFormat};
 
 pub use scaled_estimate_backend::*;
 pub use full_size_backend::*;
+pub use tone_mapping::*;
+pub use debug_view::*;
+pub use dynamic_resolution::*;
+pub use switchable_backend::*;
+pub use interleaved_backend::*;
+pub use distance_prepass::*;
 
 use super::MAX_ROM_SIZE;
 
 mod scaled_estimate_backend;
 mod full_size_backend;
+mod tone_mapping;
+mod debug_view;
+mod dynamic_resolution;
+mod switchable_backend;
+mod interleaved_backend;
+mod distance_prepass;
 pub trait RayMarcherBackend{
-    fn new(ctx: &mut Context) -> Self;
+    fn new(ctx: &mut Context) -> Self
+        where Self: Sized;
     fn resize(&mut self, ctx: &mut miniquad::Context, width: f32, height: f32);
+    /// Draws the scene into the default framebuffer, color and depth, the frame is committed by the `Renderer`.
     fn render(&mut self, ctx: &mut Context);
     fn set_elapsed(&mut self, time: f32);
+    fn set_frame_index(&mut self, frame: u32);
     fn set_position(&mut self, position: [f32;3]);
     fn set_rotation(&mut self, rotation: [f32;4]);
     fn get_scene_rom(&mut self) -> &mut [u32];
     fn recreate_scene_shader(&mut self, ctx: &mut Context, fragment: String);
+    /// Images the scene shader samples, by uniform name. Takes effect with the next `recreate_scene_shader`.
+    fn set_scene_images(&mut self, images: Vec<(String, Texture)>);
+    /// Called when the scene changed, backends that build up an image over several frames start over.
+    fn reset_accumulation(&mut self){}
+    /// Called when the backend renders again after others did, like when a `SwitchableBackend` switches back to it.
+    /// Backends that time their frames start over instead of counting the pause as one long frame.
+    fn resume(&mut self){}
+    /// `Some` keeps the scene in a float target and tone maps it to sRGB when presenting,
+    /// `None` shows colors as they come out of the scene shader.
+    fn set_tone_mapping(&mut self, tone_mapping: Option<ToneMapping>);
+    /// `Some` shows the chosen view of the primary rays instead of the shaded scene.
+    fn set_debug_view(&mut self, view: Option<DebugView>);
+}
+
+/// Uniforms and images every scene shader is compiled with.
+fn scene_shader_meta(images: &[(String, Texture)]) -> ShaderMeta{
+    ShaderMeta{
+        images: images.iter().map(|(name,_)| name.clone()).collect(),
+        uniforms: UniformBlockLayout{
+            uniforms: vec![
+                UniformDesc::new("fov_y",UniformType::Float1),
+                UniformDesc::new("elapsed_time", UniformType::Float1),
+                UniformDesc::new("frame_index", UniformType::Int1),
+                UniformDesc::new("debug_view", UniformType::Int1),
+                UniformDesc::new("march_mode", UniformType::Int1),
+                UniformDesc::new("cone_radius", UniformType::Float1),
+                UniformDesc::new("position", UniformType::Float3),
+                UniformDesc::new("rotation", UniformType::Float4),
+                UniformDesc::new("jitter", UniformType::Float2),
+                UniformDesc::new("scene_rom", UniformType::Int1).array(MAX_ROM_SIZE)
+            ],
+        }
+    }
 }
 
 #[repr(C)]
 struct SceneUniformShader{
     pub fov_y: f32,
     pub elapsed_time: f32,
+    pub frame_index: i32,
+    pub debug_view: i32,
+    /// One of the `MARCH_*` modes, picks between the cone prepass and seeding the march with its result
+    pub march_mode: i32,
+    /// Radius of the prepass cones per unit of distance along their ray
+    pub cone_radius: f32,
     pub position: [f32;3],
     pub rotation: [f32;4],
+    /// Sub-pixel offset of the primary rays, in the same units as `f_pos`
+    pub jitter: [f32;2],
     pub scene_rom: [u32;MAX_ROM_SIZE]
 }
 
@@ -31,14 +87,85 @@ impl SceneUniformShader{
     pub fn new() -> Self{
         Self{
             elapsed_time: 0.0,
+            frame_index: 0,
+            debug_view: 0,
+            march_mode: MARCH_FULL,
+            cone_radius: 0.0,
             fov_y: 1.0,
             position: [0.0,0.0,0.0],
             rotation: [0.0,0.0,0.0,1.0],
+            jitter: [0.0,0.0],
             scene_rom: [0;MAX_ROM_SIZE]
         }
     }
 }
 
+/// Scene shaders write `gl_FragDepth`, which only sticks with depth writes enabled.
+fn scene_pipeline_params() -> PipelineParams{
+    PipelineParams{
+        depth_write: true,
+        ..Default::default()
+    }
+}
+
+fn new_depth_render_texture(ctx: &mut Context, width: u32, height: u32) -> Texture{
+    let depth = Texture::new_render_texture(ctx, TextureParams{
+        width,
+        height,
+        format: TextureFormat::Depth,
+        ..Default::default()
+    });
+    depth.set_filter(ctx, FilterMode::Nearest);
+    depth
+}
+
+/// Render target storing 16 bit float colors, miniquad itself only creates 8 bit ones.
+fn new_float_render_texture(ctx: &mut Context, width: u32, height: u32) -> Texture{
+    let texture = Texture::new_render_texture(ctx, TextureParams{
+        width,
+        height,
+        ..Default::default()
+    });
+    unsafe{
+        gl::glActiveTexture(gl::GL_TEXTURE0);
+        gl::glBindTexture(gl::GL_TEXTURE_2D, texture.gl_internal_id());
+        gl::glTexImage2D(gl::GL_TEXTURE_2D, 0, gl::GL_RGBA16F as i32, width as i32, height as i32, 0, gl::GL_RGBA, gl::GL_FLOAT, std::ptr::null());
+    }
+    // Goes through miniquad's binding cache, which puts back the texture it thinks is bound
+    texture.set_filter(ctx, FilterMode::Nearest);
+    texture
+}
+
+/// Full screen quad with `f_uv` going from 0 to 1 across the target.
+const TEXTURE_VERTEX_SHADER: &str = 
+"#version 330
+in vec2 pos;
+
+out vec2 f_uv;
+
+void main(){
+    gl_Position = vec4(pos,0.1,1.0);
+    f_uv = pos * 0.5 + 0.5;
+}";
+
+/// Tone maps a float target and its depth onto the screen.
+fn present_fragment_shader() -> String{
+    format!("#version 330
+
+in vec2 f_uv;
+
+out vec4 f_color;
+
+uniform sampler2D tex;
+uniform sampler2D depth_tex;
+{}
+void main(){{
+    f_color = vec4(tone_map(texture(tex, f_uv).rgb), 1.0);
+    gl_FragDepth = texture(depth_tex, f_uv).r;
+}}
+", TONE_MAP_SOURCE)
+}
+
 const VERTS: [f32;8] = [
     -1.0,-1.0,
     1.0,-1.0,
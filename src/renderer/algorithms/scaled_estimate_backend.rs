@@ -1,10 +1,10 @@
-use miniquad::{Pipeline, Bindings, RenderPass, Context, BufferType, Buffer, Shader, UniformBlockLayout, UniformDesc, BufferLayout, VertexAttribute, VertexFormat, Texture, TextureParams, FilterMode, UniformType, ShaderMeta, PassAction};
+use std::time::Instant;
 
-use crate::renderer::MAX_ROM_SIZE;
+use miniquad::{Pipeline, Bindings, RenderPass, Context, BufferType, Buffer, Shader, UniformBlockLayout, BufferLayout, VertexAttribute, VertexFormat, Texture, ShaderMeta, PassAction};
 
-use super::{SceneUniformShader, RayMarcherBackend, VERTS, INDICES};
+use super::{SceneUniformShader, RayMarcherBackend, VERTS, INDICES, scene_shader_meta, scene_pipeline_params, new_float_render_texture, new_depth_render_texture, ToneMapping, ToneMapUniforms, TONE_MAP_SOURCE, DebugView, debug_view_code, DynamicResolution, ScaleController, DistancePrepass};
 
-const VERTEX_SHADER: &'static str = 
+const VERTEX_SHADER: &str = 
 "#version 330
 in vec2 pos;
 
@@ -18,7 +18,7 @@ void main(){
 }";
 
 
-const FRAGMENT_SHADER: &'static str = 
+const FRAGMENT_SHADER: &str = 
 "#version 330
 
 in vec2 f_pos;
@@ -132,92 +132,62 @@ void main(){
 ";
 
 
-pub const SCREEN_SCALING: u32 = 2;
+/// Screen pixels per side of a scene pixel when the backend is created through `RayMarcherBackend::new`.
+pub const DEFAULT_SCREEN_SCALING: f32 = 2.0;
 
 pub struct ScaledEstimateBackend{
     scene_pipeline: Pipeline,
     scene_bind: Bindings,
+    scene_images: Vec<(String, Texture)>,
     scene_pass: RenderPass,
+    /// Screen pixels per side of a scene pixel
+    scale: f32,
+    /// Scale the targets and display pipeline were built for, they are rebuilt before rendering when it changed
+    built_scale: f32,
+    /// Size of the window in screen pixels
+    size: (f32, f32),
+    dynamic_resolution: Option<ScaleController>,
+    last_frame: Option<Instant>,
+    distance_prepass: DistancePrepass,
 
     display_pipeline: Pipeline,
     display_bind: Bindings,
 
     uniforms: SceneUniformShader,
+    tone_mapping: Option<ToneMapping>,
 }
 
 impl RayMarcherBackend for ScaledEstimateBackend{
     
     fn new(ctx: &mut miniquad::Context) -> Self {
-        let render_width = 800 / SCREEN_SCALING;
-        let render_height = 600 / SCREEN_SCALING;
-
-        let (color, depth) = Self::get_render_textures(ctx, render_width, render_height);
-
-        let scene_pass = RenderPass::new(ctx, color, depth);
-
-        let vertex_buffer = Buffer::immutable(ctx, BufferType::VertexBuffer, &VERTS);
-        let index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, &INDICES);
-
-        let scene_bind = Bindings{
-            vertex_buffers: vec![vertex_buffer.clone()],
-            index_buffer: index_buffer.clone(),
-            images: vec![]
-        };
-
-        let scene_shader = Shader::new(ctx, VERTEX_SHADER, &FRAGMENT_SHADER,ShaderMeta{
-            images: vec![],
-            uniforms: UniformBlockLayout{
-                uniforms: vec![
-                    UniformDesc::new("fov_y",UniformType::Float1),
-                    UniformDesc::new("elapsed_time", UniformType::Float1),
-                    UniformDesc::new("position", UniformType::Float3),
-                    UniformDesc::new("rotation", UniformType::Float4),
-                    UniformDesc::new("scene_rom", UniformType::Int1).array(MAX_ROM_SIZE)
-                ],
-            }
-        }).unwrap_or_else(|e| panic!("Failed to compile scene shader: {}",e));
-
-        let scene_pipeline = Pipeline::new(
-            ctx, 
-            &[BufferLayout::default()], 
-            &[
-                VertexAttribute::new("pos", VertexFormat::Float2)
-            ],
-        scene_shader);
-
-
-        //Window renderer
-        let display_bind = Bindings{
-            vertex_buffers: vec![vertex_buffer],
-            index_buffer,
-            images: vec![color]
-        };
-
-        let display_pipeline = Self::get_display_pipeline(ctx, 800.0, 600.0);
-
-        Self{
-            scene_pipeline,
-            scene_bind,
-            scene_pass,
-
-            display_bind,
-            display_pipeline,
-            uniforms: SceneUniformShader{
-                fov_y: 600.0/800.0,
-                elapsed_time: 0.0,
-                position: [0.0,0.0,0.0],
-                rotation: [0.0,0.0,0.0,1.0],
-                scene_rom: [0;MAX_ROM_SIZE]
-            },
-        }
+        let size = ctx.screen_size();
+        Self::with_scale(ctx, DEFAULT_SCREEN_SCALING, size)
     }
 
     fn resize(&mut self, ctx: &mut miniquad::Context, width: f32, height: f32) {
         self.uniforms.fov_y = height / width;
-        self.recreate_scene_targets(ctx, width, height)   
+        self.size = (width, height);
+        self.recreate_scene_targets(ctx)
+    }
+
+    fn resume(&mut self) {
+        self.last_frame = None;
     }
 
     fn render(&mut self, ctx: &mut miniquad::Context) {
+        let now = Instant::now();
+        if let (Some(controller), Some(last_frame)) = (self.dynamic_resolution.as_mut(), self.last_frame){
+            self.scale = controller.update((now - last_frame).as_secs_f32(), self.scale);
+        }
+        self.last_frame = Some(now);
+
+        if self.built_scale != self.scale{
+            self.recreate_scene_targets(ctx);
+        }
+
+        let scene_size = self.render_size();
+        self.distance_prepass.render(ctx, &self.scene_pipeline, &mut self.scene_bind, &mut self.uniforms, scene_size);
+
         ctx.begin_pass(self.scene_pass, PassAction::clear_color(0.0, 0.0, 0.0, 0.0));
         ctx.apply_pipeline(&self.scene_pipeline);
         ctx.apply_bindings(&self.scene_bind);
@@ -228,41 +198,35 @@ impl RayMarcherBackend for ScaledEstimateBackend{
         ctx.begin_default_pass(PassAction::clear_color(1.0, 1.0, 1.0, 1.0));
         ctx.apply_pipeline(&self.display_pipeline);
         ctx.apply_bindings(&self.display_bind);
+        // Debug colors are shown as they are
+        ctx.apply_uniforms(&ToneMapUniforms::new(self.tone_mapping.filter(|_| self.uniforms.debug_view == 0)));
         ctx.draw(0, 6, 1);
         ctx.end_render_pass();
-
-        ctx.commit_frame();
     }
 
     fn set_elapsed(&mut self, time: f32) {
         self.uniforms.elapsed_time = time;
     }
 
+    fn set_frame_index(&mut self, frame: u32) {
+        self.uniforms.frame_index = frame as i32;
+    }
+
     fn get_scene_rom(&mut self) -> &mut [u32] {
         &mut self.uniforms.scene_rom[..]
     }
 
     fn recreate_scene_shader(&mut self, ctx: &mut Context, fragment: String){
-        let scene_shader = Shader::new(ctx, VERTEX_SHADER, &fragment,ShaderMeta{
-            images: vec![],
-            uniforms: UniformBlockLayout{
-                uniforms: vec![
-                    UniformDesc::new("fov_y",UniformType::Float1),
-                    UniformDesc::new("elapsed_time", UniformType::Float1),
-                    UniformDesc::new("position", UniformType::Float3),
-                    UniformDesc::new("rotation", UniformType::Float4),
-                    UniformDesc::new("scene_rom", UniformType::Int1).array(MAX_ROM_SIZE)
-                ],
-            }
-        }).unwrap_or_else(|e| panic!("Failed to compile scene shader: {}",e));
-
-        let scene_pipeline = Pipeline::new(
+        let scene_shader = Shader::new(ctx, VERTEX_SHADER, &fragment,scene_shader_meta(&self.distance_prepass.scene_images(&self.scene_images))).unwrap_or_else(|e| panic!("Failed to compile scene shader: {}",e));
+
+        let scene_pipeline = Pipeline::with_params(
             ctx, 
             &[BufferLayout::default()], 
             &[
                 VertexAttribute::new("pos", VertexFormat::Float2)
             ],
-        scene_shader);
+            scene_shader,
+            scene_pipeline_params());
 
         self.scene_pipeline = scene_pipeline;
     }
@@ -274,26 +238,147 @@ impl RayMarcherBackend for ScaledEstimateBackend{
     fn set_rotation(&mut self, rotation: [f32;4]) {
         self.uniforms.rotation = rotation;
     }
+
+    fn set_scene_images(&mut self, images: Vec<(String, Texture)>) {
+        self.scene_bind.images = self.distance_prepass.scene_images(&images).iter().map(|(_,texture)| *texture).collect();
+        self.scene_images = images;
+    }
+
+    fn set_tone_mapping(&mut self, tone_mapping: Option<ToneMapping>) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    fn set_debug_view(&mut self, view: Option<DebugView>) {
+        self.uniforms.debug_view = debug_view_code(view);
+    }
 }
 
 impl ScaledEstimateBackend{
-    fn recreate_scene_targets(&mut self, ctx: &mut Context, width: f32, height: f32){
+    /// Creates the backend for a window of `size` screen pixels, marching one ray per `scale` pixels along each side.
+    /// Fractional scales are fine, the scene target is rounded up to cover the whole window.
+    pub fn with_scale(ctx: &mut Context, scale: f32, size: (f32, f32)) -> Self{
+        assert!(scale > 0.0, "Screen scaling has to be positive");
+        let (render_width, render_height) = Self::target_size(size, scale);
+
+        let (color, depth) = Self::get_render_textures(ctx, render_width, render_height);
+
+        let scene_pass = RenderPass::new(ctx, color, depth);
 
-        let scaled_width = (width as u32) / SCREEN_SCALING;
-        let scaled_height = (height as u32) / SCREEN_SCALING;
+        let vertex_buffer = Buffer::immutable(ctx, BufferType::VertexBuffer, &VERTS);
+        let index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, &INDICES);
+
+        let distance_prepass = DistancePrepass::new(ctx);
+        let scene_images = distance_prepass.scene_images(&[]);
+
+        let scene_bind = Bindings{
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: scene_images.iter().map(|(_,texture)| *texture).collect()
+        };
+
+        let scene_shader = Shader::new(ctx, VERTEX_SHADER, FRAGMENT_SHADER,scene_shader_meta(&scene_images)).unwrap_or_else(|e| panic!("Failed to compile scene shader: {}",e));
+
+        let scene_pipeline = Pipeline::with_params(
+            ctx, 
+            &[BufferLayout::default()], 
+            &[
+                VertexAttribute::new("pos", VertexFormat::Float2)
+            ],
+            scene_shader,
+            scene_pipeline_params());
+
+
+        //Window renderer
+        let display_bind = Bindings{
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: vec![color, depth]
+        };
+
+        let display_pipeline = Self::get_display_pipeline(ctx, size, scale);
+
+        let mut uniforms = SceneUniformShader::new();
+        uniforms.fov_y = size.1 / size.0;
+
+        Self{
+            scene_pipeline,
+            scene_bind,
+            scene_images: Vec::new(),
+            scene_pass,
+
+            display_bind,
+            display_pipeline,
+            scale,
+            built_scale: scale,
+            size,
+            dynamic_resolution: None,
+            last_frame: None,
+            distance_prepass,
+            uniforms,
+            tone_mapping: None,
+        }
+    }
+
+    pub fn scale(&self) -> f32{
+        self.scale
+    }
+
+    /// Changes how many screen pixels a scene pixel covers, the targets and display pipeline are rebuilt with the next frame.
+    /// With dynamic resolution on, this is only where the backend starts adjusting from.
+    pub fn set_scale(&mut self, scale: f32){
+        assert!(scale > 0.0, "Screen scaling has to be positive");
+        self.scale = scale;
+    }
+
+    /// `Some` lets the backend adjust its scale every frame to stay within the frame time budget,
+    /// `None` keeps the current scale.
+    pub fn set_dynamic_resolution(&mut self, settings: Option<DynamicResolution>){
+        self.dynamic_resolution = settings.map(|settings| {
+            self.scale = self.scale.clamp(settings.min_scale, settings.max_scale);
+            ScaleController::new(settings)
+        });
+    }
+
+    pub fn dynamic_resolution(&self) -> Option<&ScaleController>{
+        self.dynamic_resolution.as_ref()
+    }
+
+    /// `Some` cone marches tiles of `tile_size` scene pixels before every frame and starts the rays of each tile
+    /// where the cone first came close to a surface, `None` marches every ray from the camera.
+    pub fn set_distance_prepass(&mut self, tile_size: Option<u32>){
+        self.distance_prepass.set_tile_size(tile_size);
+    }
+
+    pub fn distance_prepass(&self) -> Option<u32>{
+        self.distance_prepass.tile_size()
+    }
+
+    /// Size of the target the scene is marched into.
+    pub fn render_size(&self) -> (u32, u32){
+        Self::target_size(self.size, self.scale)
+    }
+
+    fn target_size(size: (f32, f32), scale: f32) -> (u32, u32){
+        ((size.0 / scale).ceil().max(1.0) as u32, (size.1 / scale).ceil().max(1.0) as u32)
+    }
+
+    fn recreate_scene_targets(&mut self, ctx: &mut Context){
+        self.built_scale = self.scale;
+        let (scaled_width, scaled_height) = self.render_size();
 
         //Offscren renderer
+        self.scene_pass.delete(ctx);
         let (color, depth) = Self::get_render_textures(ctx, scaled_width, scaled_height);
 
         let scene_pass = RenderPass::new(ctx, color, depth);
 
         let display_bind = Bindings{
-            vertex_buffers: vec![self.display_bind.vertex_buffers[0].clone()],
-            index_buffer: self.display_bind.index_buffer.clone(),
-            images: vec![color]
+            vertex_buffers: vec![self.display_bind.vertex_buffers[0]],
+            index_buffer: self.display_bind.index_buffer,
+            images: vec![color, depth]
         };
 
-        let display_pipeline = Self::get_display_pipeline(ctx, width, height);
+        let display_pipeline = Self::get_display_pipeline(ctx, self.size, self.scale);
 
         self.scene_pass = scene_pass;
         self.display_bind = display_bind;
@@ -301,51 +386,40 @@ impl ScaledEstimateBackend{
     }
 
     fn get_render_textures(ctx: &mut Context, width: u32, height: u32) -> (Texture,Texture){
-        let color = Texture::new_render_texture(ctx, TextureParams{
-            width,
-            height,
-            format: miniquad::TextureFormat::RGBA8,
-            ..Default::default()
-        });
+        // Float so colors above 1 survive until the display pass tone maps them
+        let color = new_float_render_texture(ctx, width, height);
 
-        let depth = Texture::new_render_texture(ctx,TextureParams{
-            width,
-            height,
-            format: miniquad::TextureFormat::Depth,
-            ..Default::default()
-        });
-
-        color.set_filter(ctx, FilterMode::Nearest);
-        depth.set_filter(ctx, FilterMode::Nearest);
+        let depth = new_depth_render_texture(ctx, width, height);
         
         (color,depth)
     }
 
-    fn get_display_pipeline(ctx: &mut Context, width: f32, height: f32) -> Pipeline{
+    fn get_display_pipeline(ctx: &mut Context, size: (f32, f32), scale: f32) -> Pipeline{
 
-        let vertex = Self::get_display_vertex_shader(width, height);
-        let fragment = Self::get_display_fragment(width, height);
+        let vertex = Self::get_display_vertex_shader(size, scale);
+        let fragment = Self::get_display_fragment(scale);
         let display_shader = Shader::new(ctx, &vertex, &fragment, ShaderMeta{
             uniforms: UniformBlockLayout{
-                uniforms: Vec::new()
+                uniforms: ToneMapUniforms::uniform_descs()
             },
-            images: vec!["tex".to_string()]
-        }).unwrap_or_else(|e| panic!("Failed to compile display shader: {}",e.to_string()));
+            images: vec!["tex".to_string(), "depth_tex".to_string()]
+        }).unwrap_or_else(|e| panic!("Failed to compile display shader: {}",e));
 
 
-        let display_pipeline = Pipeline::new(
+        Pipeline::with_params(
             ctx, 
             &[BufferLayout::default()], 
             &[
                 VertexAttribute::new("pos", VertexFormat::Float2)
             ],
-            display_shader);
-
-        display_pipeline
+            display_shader,
+            scene_pipeline_params())
     }
 
 
-    fn get_display_vertex_shader(width: f32, height: f32) -> String{
+    /// `f_pos` is the position in screen pixels and `f_texel` the same position in scene pixels,
+    /// so scene pixel `i` covers screen pixels `i * scale` up to `(i + 1) * scale` for any scale.
+    fn get_display_vertex_shader(size: (f32, f32), scale: f32) -> String{
         format!("#version 330
         in vec2 pos;
         out vec2 f_pos;
@@ -354,21 +428,21 @@ impl ScaledEstimateBackend{
         void main(){{
             
             gl_Position = vec4(pos,0.1,1.0);
-            f_texel = vec2(floor((pos.x * {0} + {0}) / {2}),       floor((pos.y * {1} + {1}) / {2}) );
-            f_pos = vec2(  floor((pos.x * {0} + {0}) / {2}) * {2}, floor((pos.y * {1} + {1}) / {2}) * {2});
-            //f_pos = (pos * 0.5) + vec2(0.5,0.5);
+            f_pos = (pos * 0.5 + 0.5) * vec2({0:?}, {1:?});
+            f_texel = f_pos / {2:?};
         }}
-        ",width / 2.0, height / 2.0, SCREEN_SCALING)
+        ",size.0, size.1, scale)
     }
 
-    fn get_display_fragment(width: f32, height: f32) -> String{
+    fn get_display_fragment(scale: f32) -> String{
         format!(
             "#version 330
 in vec2 f_pos;
 in vec2 f_texel;
 
 uniform sampler2D tex;
-
+uniform sampler2D depth_tex;
+{2}
 out vec4 f_color;
 
 void main(){{
@@ -480,13 +554,14 @@ void main(){{
         }}
     }}
 
-    f_color.a = 1.0;
+    f_color = vec4(tone_map(f_color.rgb), 1.0);
+    gl_FragDepth = texelFetch(depth_tex, pixel + ivec2(1,1), 0).r;
 
 
     //f_color = textureBicubic(tex,f_pos);
 
 }}
 
-",SCREEN_SCALING, SCREEN_SCALING as f32 / 2.0)
+",format!("{:?}", scale), format!("{:?}", scale / 2.0), TONE_MAP_SOURCE)
     }
 }
\ No newline at end of file
@@ -0,0 +1,148 @@
+use std::any::Any;
+
+use miniquad::{Context, Texture};
+
+use super::{RayMarcherBackend, FullSizeBackend, ScaledEstimateBackend, InterleavedBackend, ToneMapping, DebugView};
+
+/// A backend held by `SwitchableBackend`, which can hand it back as its own type for its settings.
+pub trait SwitchableEntry: RayMarcherBackend{
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: RayMarcherBackend + 'static> SwitchableEntry for T{
+    fn as_any(&self) -> &dyn Any{
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any{
+        self
+    }
+}
+
+/// Holds several backends and renders with one of them at a time, for comparing them side by side while running.
+///
+/// Everything the `Renderer` and app set is passed on to every backend, so a switch only has to carry the scene rom over.
+pub struct SwitchableBackend{
+    backends: Vec<Box<dyn SwitchableEntry>>,
+    active: usize,
+}
+
+impl SwitchableBackend{
+    pub fn with_backends(backends: Vec<Box<dyn SwitchableEntry>>) -> Self{
+        assert!(!backends.is_empty(), "SwitchableBackend needs at least one backend");
+        Self{
+            backends,
+            active: 0
+        }
+    }
+
+    pub fn active(&self) -> usize{
+        self.active
+    }
+
+    pub fn len(&self) -> usize{
+        self.backends.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.backends.is_empty()
+    }
+
+    /// Renders with the backend at `index` from the next frame on.
+    pub fn switch_to(&mut self, index: usize){
+        assert!(index < self.backends.len(), "No backend at index {}", index);
+        if index == self.active{
+            return;
+        }
+        let rom = self.backends[self.active].get_scene_rom().to_vec();
+        self.active = index;
+        let backend = self.active_mut();
+        backend.get_scene_rom().copy_from_slice(&rom);
+        backend.reset_accumulation();
+        backend.resume();
+    }
+
+    /// Switches to the backend after the active one, wrapping around.
+    pub fn switch_next(&mut self){
+        self.switch_to((self.active + 1) % self.backends.len());
+    }
+
+    pub fn active_mut(&mut self) -> &mut dyn RayMarcherBackend{
+        self.backends[self.active].as_mut()
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut dyn RayMarcherBackend{
+        self.backends[index].as_mut()
+    }
+
+    /// The first held backend of type `T`, for settings only that backend has.
+    pub fn backend<T: RayMarcherBackend + 'static>(&self) -> Option<&T>{
+        self.backends.iter().find_map(|x| x.as_any().downcast_ref())
+    }
+
+    /// The first held backend of type `T`, for settings only that backend has.
+    pub fn backend_mut<T: RayMarcherBackend + 'static>(&mut self) -> Option<&mut T>{
+        self.backends.iter_mut().find_map(|x| x.as_any_mut().downcast_mut())
+    }
+}
+
+impl RayMarcherBackend for SwitchableBackend{
+    /// Starts with a `FullSizeBackend`, followed by a `ScaledEstimateBackend`.
+    fn new(ctx: &mut Context) -> Self {
+        Self::with_backends(vec![
+            Box::new(FullSizeBackend::new(ctx)),
+            Box::new(ScaledEstimateBackend::new(ctx)),
+            Box::new(InterleavedBackend::new(ctx)),
+        ])
+    }
+
+    fn resize(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.backends.iter_mut().for_each(|x| x.resize(ctx, width, height));
+    }
+
+    fn render(&mut self, ctx: &mut Context) {
+        self.active_mut().render(ctx);
+    }
+
+    fn set_elapsed(&mut self, time: f32) {
+        self.backends.iter_mut().for_each(|x| x.set_elapsed(time));
+    }
+
+    fn set_frame_index(&mut self, frame: u32) {
+        self.backends.iter_mut().for_each(|x| x.set_frame_index(frame));
+    }
+
+    fn set_position(&mut self, position: [f32;3]) {
+        self.backends.iter_mut().for_each(|x| x.set_position(position));
+    }
+
+    fn set_rotation(&mut self, rotation: [f32;4]) {
+        self.backends.iter_mut().for_each(|x| x.set_rotation(rotation));
+    }
+
+    /// Rom of the active backend, the others get a copy when they are switched to.
+    fn get_scene_rom(&mut self) -> &mut [u32] {
+        self.active_mut().get_scene_rom()
+    }
+
+    fn recreate_scene_shader(&mut self, ctx: &mut Context, fragment: String) {
+        self.backends.iter_mut().for_each(|x| x.recreate_scene_shader(ctx, fragment.clone()));
+    }
+
+    fn set_scene_images(&mut self, images: Vec<(String, Texture)>) {
+        self.backends.iter_mut().for_each(|x| x.set_scene_images(images.clone()));
+    }
+
+    fn reset_accumulation(&mut self) {
+        self.active_mut().reset_accumulation();
+    }
+
+    fn set_tone_mapping(&mut self, tone_mapping: Option<ToneMapping>) {
+        self.backends.iter_mut().for_each(|x| x.set_tone_mapping(tone_mapping));
+    }
+
+    fn set_debug_view(&mut self, view: Option<DebugView>) {
+        self.backends.iter_mut().for_each(|x| x.set_debug_view(view));
+    }
+}
@@ -0,0 +1,101 @@
+use miniquad::{UniformDesc, UniformType};
+
+/// Curve used to bring scene colors into the displayable range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapOperator{
+    /// Only scales by the exposure, anything above 1 clips.
+    Exposure,
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+}
+
+impl ToneMapOperator{
+    fn code(&self) -> i32{
+        match self{
+            ToneMapOperator::Exposure => 1,
+            ToneMapOperator::Reinhard => 2,
+            ToneMapOperator::Aces => 3,
+        }
+    }
+}
+
+/// Tone mapping of a backend rendering into a float target, the result is sRGB encoded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapping{
+    pub operator: ToneMapOperator,
+    /// Scene colors are multiplied by this before the curve is applied.
+    pub exposure: f32,
+}
+
+impl Default for ToneMapping{
+    fn default() -> Self {
+        Self{
+            operator: ToneMapOperator::Aces,
+            exposure: 1.0
+        }
+    }
+}
+
+impl ToneMapping{
+    /// CPU version of `tone_map`.
+    pub fn apply(&self, color: [f32;3]) -> [f32;3]{
+        let srgb = |x: f32| if x < 0.0031308 { x * 12.92 } else { 1.055 * x.powf(1.0 / 2.4) - 0.055 };
+        color.map(|x| {
+            let x = (x * self.exposure).max(0.0);
+            srgb(match self.operator{
+                ToneMapOperator::Exposure => x.clamp(0.0, 1.0),
+                ToneMapOperator::Reinhard => x / (1.0 + x),
+                ToneMapOperator::Aces => ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0),
+            })
+        })
+    }
+}
+
+/// GLSL `vec3 tone_map(vec3 color)`, with no tone mapping set colors are passed through untouched.
+pub const TONE_MAP_SOURCE: &str = "
+uniform float exposure;
+uniform int tone_map_operator;
+
+vec3 linear_to_srgb(vec3 color){
+    return mix(color * 12.92, 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055, step(vec3(0.0031308), color));
+}
+
+vec3 tone_map(vec3 color){
+    color = max(color * exposure, vec3(0.0));
+    switch (tone_map_operator){
+        case 1: return linear_to_srgb(clamp(color, 0.0, 1.0));
+        case 2: return linear_to_srgb(color / (1.0 + color));
+        case 3: return linear_to_srgb(clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0));
+        default: return color;
+    }
+}
+";
+
+#[repr(C)]
+pub(crate) struct ToneMapUniforms{
+    pub exposure: f32,
+    pub operator: i32,
+}
+
+impl ToneMapUniforms{
+    pub fn new(tone_mapping: Option<ToneMapping>) -> Self{
+        match tone_mapping{
+            Some(tone_mapping) => Self{
+                exposure: tone_mapping.exposure,
+                operator: tone_mapping.operator.code()
+            },
+            None => Self{
+                exposure: 1.0,
+                operator: 0
+            },
+        }
+    }
+
+    pub fn uniform_descs() -> Vec<UniformDesc>{
+        vec![
+            UniformDesc::new("exposure", UniformType::Float1),
+            UniformDesc::new("tone_map_operator", UniformType::Int1),
+        ]
+    }
+}
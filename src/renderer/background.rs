@@ -0,0 +1,136 @@
+use miniquad::{Context, Texture};
+
+use super::math::{Vec3, vec2, Vec2, smoothstep};
+
+/// Sampler uniform the faces of `Background::Cubemap` are bound to.
+pub const CUBEMAP_UNIFORM: &str = "background_cubemap";
+
+/// What rays that hit nothing see, the generated shader calls it as `vec3 background(vec3 ray)`.
+pub enum Background{
+    Solid([f32;3]),
+    /// Blends from the horizon color straight ahead up to the zenith color straight up.
+    Gradient{ horizon: [f32;3], zenith: [f32;3] },
+    /// Analytic blue sky with a sun, the sun also becomes the direction scenes are lit from.
+    Sky{ sun_direction: [f32;3], sun_color: [f32;3] },
+    /// Six square faces side by side as rgba8, ordered +X, -X, +Y, -Y, +Z, -Z with the top row first.
+    Cubemap{ face_size: u16, rgba: Vec<u8> },
+    /// A method added through `Renderer::add_methods`, called as `vec3 name(vec3 ray)`.
+    Method(String),
+}
+
+impl Default for Background{
+    fn default() -> Self {
+        Background::Gradient{
+            horizon: [0.8, 0.85, 0.9],
+            zenith: [0.35, 0.5, 0.8]
+        }
+    }
+}
+
+impl Background{
+    /// The `background` function of the scene shader, along with any uniforms it needs.
+    pub fn source(&self) -> String{
+        let call = match self{
+            Background::Solid(color) => format!("background_solid(ray, {})", glsl_vec3(*color)),
+            Background::Gradient{ horizon, zenith } => format!("background_gradient(ray, {}, {})", glsl_vec3(*horizon), glsl_vec3(*zenith)),
+            Background::Sky{ sun_direction, sun_color } => format!("background_sky(ray, {}, {})", glsl_vec3(normalize(*sun_direction)), glsl_vec3(*sun_color)),
+            Background::Cubemap{ .. } => format!("texture({}, cubemap_strip_uv(ray)).rgb", CUBEMAP_UNIFORM),
+            Background::Method(name) => format!("{}(ray)", name),
+        };
+        let uniforms = match self{
+            Background::Cubemap{ .. } => format!("uniform sampler2D {};", CUBEMAP_UNIFORM),
+            _ => String::new(),
+        };
+
+        format!("{}
+        {}
+
+        vec3 background(in vec3 ray){{
+            return {};
+        }}", uniforms, include_str!("../../sdf/background.glsl"), call)
+    }
+
+    /// Direction the scene is lit from, if the background has one.
+    pub fn light_direction(&self) -> Option<[f32;3]>{
+        match self{
+            Background::Sky{ sun_direction, .. } => Some(normalize(*sun_direction)),
+            _ => None,
+        }
+    }
+
+    /// Images the scene shader samples, by uniform name.
+    pub fn create_images(&self, ctx: &mut Context) -> Vec<(String, Texture)>{
+        match self{
+            Background::Cubemap{ face_size, rgba } => {
+                assert_eq!(rgba.len(), *face_size as usize * *face_size as usize * 6 * 4, "Cubemap data doesn't match six faces of {}x{}", face_size, face_size);
+                let texture = Texture::from_rgba8(ctx, face_size * 6, *face_size, rgba);
+                vec![(CUBEMAP_UNIFORM.to_string(), texture)]
+            },
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Background{
+    /// CPU version of the generated `background` function. Cubemaps are sampled without filtering.
+    ///
+    /// Panics for `Background::Method`, as there is no Rust code to run for it.
+    pub fn evaluate(&self, ray: Vec3) -> Vec3{
+        match self{
+            Background::Solid(color) => (*color).into(),
+            Background::Gradient{ horizon, zenith } => Vec3::from(*horizon).mix((*zenith).into(), ray.y.clamp(0.0, 1.0)),
+            Background::Sky{ sun_direction, sun_color } => {
+                let zenith = Vec3::from([0.25, 0.45, 0.85]);
+                let horizon = Vec3::from([0.75, 0.85, 0.95]);
+                let ground = Vec3::from([0.3, 0.28, 0.25]);
+
+                let mut sky = if ray.y > 0.0 {
+                    horizon.mix(zenith, ray.y.sqrt())
+                }
+                else{
+                    horizon.mix(ground, (-ray.y).clamp(0.0, 1.0).powf(0.3))
+                };
+
+                let sun_color = Vec3::from(*sun_color);
+                let sun = ray.dot(normalize(*sun_direction).into()).max(0.0);
+                sky = sky + sun_color * (sun.powf(8.0) * 0.2 + sun.powf(64.0) * 0.3);
+                sky + sun_color * smoothstep(0.9995, 0.9998, sun)
+            },
+            Background::Cubemap{ face_size, rgba } => {
+                let uv = cubemap_strip_uv(ray);
+                let width = *face_size as usize * 6;
+                let x = ((uv.x * width as f32) as usize).min(width - 1);
+                let y = ((uv.y * *face_size as f32) as usize).min(*face_size as usize - 1);
+                let i = (y * width + x) * 4;
+                Vec3::from([rgba[i], rgba[i + 1], rgba[i + 2]].map(|x| x as f32 / 255.0))
+            },
+            Background::Method(name) => panic!("Background method {} only exists in the shader", name),
+        }
+    }
+}
+
+/// Port of `cubemap_strip_uv` in `sdf/background.glsl`.
+fn cubemap_strip_uv(ray: Vec3) -> Vec2{
+    let a = ray.abs();
+    let (face, uv) = if a.x >= a.y && a.x >= a.z {
+        (if ray.x > 0.0 { 0.0 } else { 1.0 }, vec2(if ray.x > 0.0 { -ray.z } else { ray.z }, -ray.y) * (1.0 / a.x))
+    }
+    else if a.y >= a.z {
+        (if ray.y > 0.0 { 2.0 } else { 3.0 }, vec2(ray.x, if ray.y > 0.0 { ray.z } else { -ray.z }) * (1.0 / a.y))
+    }
+    else{
+        (if ray.z > 0.0 { 4.0 } else { 5.0 }, vec2(if ray.z > 0.0 { ray.x } else { -ray.x }, -ray.y) * (1.0 / a.z))
+    };
+
+    let u = (uv.x * 0.5 + 0.5).clamp(0.002, 0.998);
+    let v = (uv.y * 0.5 + 0.5).clamp(0.002, 0.998);
+    vec2((face + u) / 6.0, v)
+}
+
+fn normalize(v: [f32;3]) -> [f32;3]{
+    Vec3::from(v).normalize().into()
+}
+
+fn glsl_vec3(v: [f32;3]) -> String{
+    format!("vec3({:?},{:?},{:?})", v[0], v[1], v[2])
+}
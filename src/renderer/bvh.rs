@@ -0,0 +1,116 @@
+//! Bounding volume hierarchy over the top level instances of a scene, serialized as skip records.
+//!
+//! A node record is `OP_BVH_NODE, skip, instance count, min.xyz, max.xyz`, followed by the `skip` words
+//! of its subtree. The shader jumps over the subtree when the ray misses the box, or when the box
+//! is farther away than the closest distance found so far.
+
+use super::{math::Vec3, scene::{Serializeable, SceneSerializer, Transform, OP_BVH_NODE}};
+
+/// Number of words in a node record.
+pub const BVH_NODE_LEN: usize = 9;
+
+/// Axis aligned box, conservative bounds of an instance in the space it is serialized in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb{
+    pub min: [f32;3],
+    pub max: [f32;3]
+}
+
+impl Aabb{
+    pub fn new(min: [f32;3], max: [f32;3]) -> Self{
+        Self{
+            min,
+            max
+        }
+    }
+
+    pub fn from_center(center: [f32;3], half_size: [f32;3]) -> Self{
+        let center = Vec3::from(center);
+        let half_size = Vec3::from(half_size);
+        Self::new((center - half_size).into(), (center + half_size).into())
+    }
+
+    /// Box of a sphere.
+    pub fn from_radius(center: [f32;3], radius: f32) -> Self{
+        Self::from_center(center, [radius;3])
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb{
+        Self::new(
+            Vec3::from(self.min).min(other.min.into()).into(),
+            Vec3::from(self.max).max(other.max.into()).into())
+    }
+
+    pub fn expanded(&self, amount: [f32;3]) -> Aabb{
+        Self::new(
+            (Vec3::from(self.min) - Vec3::from(amount)).into(),
+            (Vec3::from(self.max) + Vec3::from(amount)).into())
+    }
+
+    pub fn translated(&self, offset: [f32;3]) -> Aabb{
+        Self::new(
+            (Vec3::from(self.min) + Vec3::from(offset)).into(),
+            (Vec3::from(self.max) + Vec3::from(offset)).into())
+    }
+
+    pub fn center(&self) -> [f32;3]{
+        ((Vec3::from(self.min) + Vec3::from(self.max)) * 0.5).into()
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool{
+        (0..3).all(|axis| self.min[axis] <= other.min[axis] && other.max[axis] <= self.max[axis])
+    }
+
+    pub fn corners(&self) -> [[f32;3];8]{
+        std::array::from_fn(|i| [0, 1, 2].map(|axis| if i & (1 << axis) == 0 { self.min[axis] } else { self.max[axis] }))
+    }
+
+    /// Box around the corners moved by the transform.
+    pub fn transformed(&self, transform: &Transform) -> Aabb{
+        Self::around(self.corners().iter().map(|x| transform.apply(*x)))
+    }
+
+    /// Box around every point, which there has to be at least one of.
+    pub fn around(mut points: impl Iterator<Item = [f32;3]>) -> Aabb{
+        let first = points.next().expect("Aabb::around needs at least one point");
+        points.fold(Self::new(first, first), |aabb, x| aabb.union(&Self::new(x, x)))
+    }
+
+    /// Largest distance from `origin` to a point of the box.
+    pub fn max_distance_from(&self, origin: [f32;3]) -> f32{
+        self.corners().iter()
+            .map(|x| (Vec3::from(*x) - Vec3::from(origin)).length())
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Serializes the items as a tree of node records, splitting at the median of the longest axis of their centers.
+///
+/// Single items are written as they are, so every node holds at least two.
+pub fn serialize_bvh<'a>(items: &mut [(&dyn Serializeable, Aabb)], serializer: &mut SceneSerializer<'a>){
+    match items{
+        [] => {},
+        [(item, _)] => item.serialize(serializer),
+        _ => {
+            let bounds = items[1..].iter().fold(items[0].1, |aabb, (_, x)| aabb.union(x));
+            let centers = Aabb::around(items.iter().map(|(_, x)| x.center()));
+            let size = Vec3::from(centers.max) - Vec3::from(centers.min);
+            let axis = if size.x >= size.y && size.x >= size.z { 0 } else if size.y >= size.z { 1 } else { 2 };
+            items.sort_by(|(_, a), (_, b)| a.center()[axis].total_cmp(&b.center()[axis]));
+
+            serializer.write_value(OP_BVH_NODE as u32);
+            let skip = serializer.position();
+            serializer.write_value(0);
+            serializer.write_value(items.iter().map(|(x, _)| x.instance_count() as u32).sum());
+            bounds.min.serialize(serializer);
+            bounds.max.serialize(serializer);
+
+            let start = serializer.position();
+            let (left, right) = items.split_at_mut(items.len() / 2);
+            serialize_bvh(left, serializer);
+            serialize_bvh(right, serializer);
+            let len = serializer.position() - start;
+            serializer.patch(skip, len as u32);
+        },
+    }
+}
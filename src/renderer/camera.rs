@@ -0,0 +1,31 @@
+use super::MAX_DISTANCE;
+
+/// Distance from the camera to the screen, primary rays go through `vec3(f_pos, FOCAL_LENGTH)`.
+pub const FOCAL_LENGTH: f32 = 2.0;
+/// Closest distance written to the depth buffer, nearer hits are clamped to it.
+pub const NEAR_PLANE: f32 = 0.1;
+/// Hits at the marcher's maximum distance end up at the back of the depth buffer.
+pub const FAR_PLANE: f32 = MAX_DISTANCE;
+
+/// Column major projection matching the primary rays of the scene shader, looking down +Z.
+/// `fov_y` is the height of the screen over its width, as the backends pass it.
+pub fn projection(fov_y: f32) -> [f32;16]{
+    let depth_scale = (FAR_PLANE + NEAR_PLANE) / (FAR_PLANE - NEAR_PLANE);
+    let depth_offset = -2.0 * FAR_PLANE * NEAR_PLANE / (FAR_PLANE - NEAR_PLANE);
+    [
+        FOCAL_LENGTH, 0.0, 0.0, 0.0,
+        0.0, FOCAL_LENGTH / fov_y, 0.0, 0.0,
+        0.0, 0.0, depth_scale, 1.0,
+        0.0, 0.0, depth_offset, 0.0,
+    ]
+}
+
+/// Projection of a camera at `position`, for drawing meshes that are depth tested against the scene.
+pub fn view_projection(position: [f32;3], screen_size: (f32, f32)) -> [f32;16]{
+    let mut matrix = projection(screen_size.1 / screen_size.0);
+    // Translating by -position first only moves the last column
+    for row in 0..4{
+        matrix[12 + row] -= (0..3).map(|axis| matrix[axis * 4 + row] * position[axis]).sum::<f32>();
+    }
+    matrix
+}
@@ -0,0 +1,745 @@
+//! Software version of the generated scene shader, interpreting the same rom with the methods of a `CpuMethods`.
+//!
+//! The functions follow their GLSL counterparts in `Renderer::get_scene_shader` step by step,
+//! so a difference between the two is a bug in one of them.
+
+use std::{sync::atomic::{AtomicUsize, Ordering}, thread};
+
+use crate::renderer::{
+    HIT_DISTANCE, MAX_DISTANCE, MAX_ROM_SIZE, DEFAULT_BOUNCE_DEPTH, DEFAULT_LIGHT_DIRECTION,
+    algorithms::ToneMapping,
+    background::Background,
+    camera::FOCAL_LENGTH,
+    math::{Vec3, vec3},
+    scene::{Serializeable, SceneSerializer, Material, CsgOp, DomainModifier, Animation, OP_GROUP_BEGIN, OP_GROUP_END, OP_TRANSFORM, OP_DOMAIN, OP_ANIMATE, OP_BVH_NODE, MAX_KEYFRAMES},
+};
+
+use super::{methods::{CpuMethods, CpuMethod, RomData}, primitives::{Interval, BOUND_FAR, BOUND_MISS, bound_ray_box, interval_hit}};
+
+/// Side of the square tiles the image is split into, threads take the next unrendered tile until none are left.
+pub const TILE_SIZE: u32 = 32;
+
+const AMBIENT: f32 = 0.2;
+
+/// Rows of rgba8 pixels, top row first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CpuImage{
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>
+}
+
+impl CpuImage{
+    pub fn pixel(&self, x: u32, y: u32) -> [u8;4]{
+        let i = (y * self.width + x) as usize * 4;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct HitInfo{
+    dist: f32,
+    /// Rom index of the tex record of the instance
+    id: usize
+}
+
+impl HitInfo{
+    fn miss() -> Self{
+        Self{
+            dist: MAX_DISTANCE + 1.0,
+            id: 0
+        }
+    }
+}
+
+struct MarchInfo{
+    hit: HitInfo,
+    position: Vec3,
+    /// Bound intervals of the march, sdf_scene needs them again for the normal
+    intervals: Vec<Interval>,
+}
+
+/// Space a transform or animation record moves its child into, animations are rigid and keep a scale of 1.
+#[derive(Clone, Copy)]
+struct LocalSpace{
+    translation: Vec3,
+    inverse_rotation: [f32;4],
+    scale: Vec3
+}
+
+impl LocalSpace{
+    fn point(&self, point: Vec3) -> Vec3{
+        quat_rotate(self.inverse_rotation, point - self.translation) / self.scale
+    }
+
+    /// Not normalized, its length tells how much the space stretches the direction.
+    fn direction(&self, direction: Vec3) -> Vec3{
+        quat_rotate(self.inverse_rotation, direction) / self.scale
+    }
+
+    /// Factor distances measured in the space are scaled by to stay conservative.
+    fn distance_scale(&self) -> f32{
+        self.scale.min_element()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct BoundState{
+    origin: Vec3,
+    ray: Vec3,
+    ray_scale: f32,
+    bounded: bool,
+    blended: bool
+}
+
+struct GroupState{
+    hit: HitInfo,
+    op: i32,
+    k: f32,
+    empty: bool,
+    origin: Vec3,
+    position: Vec3,
+    ray: Vec3,
+    scale: f32
+}
+
+/// Renders scene roms without a GL context, on as many threads as the machine has.
+pub struct CpuBackend{
+    methods: CpuMethods,
+    scene_rom: Vec<u32>,
+    position: [f32;3],
+    elapsed_time: f32,
+    bounce_depth: u32,
+    background: Background,
+    fog_density: f32,
+    tone_mapping: Option<ToneMapping>,
+    threads: usize,
+}
+
+impl CpuBackend{
+    pub fn new(methods: CpuMethods) -> Self{
+        Self{
+            methods,
+            scene_rom: vec![0; MAX_ROM_SIZE],
+            position: [0.0;3],
+            elapsed_time: 0.0,
+            bounce_depth: DEFAULT_BOUNCE_DEPTH,
+            background: Background::default(),
+            fog_density: 0.0,
+            tone_mapping: None,
+            threads: thread::available_parallelism().map(|x| x.get()).unwrap_or(1),
+        }
+    }
+
+    pub fn get_scene_rom(&mut self) -> &mut [u32]{
+        &mut self.scene_rom[..]
+    }
+
+    /// Clears the rom and serializes the scene into it.
+    /// Fails when the scene doesn't fit in the rom, rendering would then only show part of it.
+    pub fn load_scene(&mut self, scene: &dyn Serializeable) -> Result<(), String>{
+        self.scene_rom.iter_mut().for_each(|x| *x = 0);
+        let mut serializer = SceneSerializer::new(&mut self.scene_rom);
+        scene.serialize(&mut serializer);
+        if serializer.overflowed() {
+            return Err(format!("The scene doesn't fit in the {} words of the scene rom", MAX_ROM_SIZE));
+        }
+        Ok(())
+    }
+
+    pub fn set_position(&mut self, position: [f32;3]){
+        self.position = position;
+    }
+
+    pub fn set_elapsed(&mut self, time: f32){
+        self.elapsed_time = time;
+    }
+
+    /// Same as `Renderer::set_bounce_depth`.
+    pub fn set_bounce_depth(&mut self, depth: u32){
+        self.bounce_depth = depth;
+    }
+
+    /// Same as `Renderer::set_background`, `Background::Method` can't be rendered on the CPU.
+    pub fn set_background(&mut self, background: Background){
+        self.background = background;
+    }
+
+    /// Same as `Renderer::set_fog_density`.
+    pub fn set_fog_density(&mut self, density: f32){
+        self.fog_density = density;
+    }
+
+    /// `None` clamps the scene colors, like a backend without tone mapping.
+    pub fn set_tone_mapping(&mut self, tone_mapping: Option<ToneMapping>){
+        self.tone_mapping = tone_mapping;
+    }
+
+    pub fn set_threads(&mut self, threads: usize){
+        self.threads = threads.max(1);
+    }
+
+    /// Renders the scene at the given size, with the same camera as the backends use for a window of that size.
+    pub fn render(&self, width: u32, height: u32) -> CpuImage{
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let tiles_y = height.div_ceil(TILE_SIZE);
+        let tile_count = (tiles_x * tiles_y) as usize;
+        let next_tile = AtomicUsize::new(0);
+
+        let tiles = thread::scope(|s|{
+            let workers = (0..self.threads.min(tile_count.max(1))).map(|_| s.spawn(||{
+                let mut done = Vec::new();
+                loop{
+                    let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if tile >= tile_count {
+                        break;
+                    }
+                    let x = (tile as u32 % tiles_x) * TILE_SIZE;
+                    let y = (tile as u32 / tiles_x) * TILE_SIZE;
+                    done.push((x, y, self.render_tile(x, y, width, height)));
+                }
+                done
+            })).collect::<Vec<_>>();
+            workers.into_iter().flat_map(|x| x.join().expect("CPU render thread panicked")).collect::<Vec<_>>()
+        });
+
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        for (x, y, tile) in tiles{
+            let tile_width = TILE_SIZE.min(width - x);
+            for (row, colors) in tile.chunks(tile_width as usize * 4).enumerate(){
+                let start = (((y + row as u32) * width + x) * 4) as usize;
+                pixels[start..start + colors.len()].copy_from_slice(colors);
+            }
+        }
+
+        CpuImage{
+            width,
+            height,
+            pixels
+        }
+    }
+
+    fn render_tile(&self, x: u32, y: u32, width: u32, height: u32) -> Vec<u8>{
+        let fov_y = height as f32 / width as f32;
+        let mut pixels = Vec::new();
+        for row in y..(y + TILE_SIZE).min(height){
+            for column in x..(x + TILE_SIZE).min(width){
+                // f_pos of the fragment at the pixel center, rows go down while gl goes up
+                let f_pos_x = (column as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+                let f_pos_y = (1.0 - (row as f32 + 0.5) / height as f32 * 2.0) * fov_y;
+                let color = self.trace(vec3(f_pos_x, f_pos_y, FOCAL_LENGTH).normalize());
+                let color = match self.tone_mapping{
+                    Some(tone_mapping) => tone_mapping.apply(color.into()),
+                    None => color.into(),
+                };
+                pixels.extend(color.map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u8));
+                pixels.push(255);
+            }
+        }
+        pixels
+    }
+
+    fn read_float(&self, pnt: usize) -> f32{
+        f32::from_bits(self.scene_rom[pnt])
+    }
+
+    fn read_vec3(&self, pnt: usize) -> Vec3{
+        vec3(self.read_float(pnt), self.read_float(pnt + 1), self.read_float(pnt + 2))
+    }
+
+    fn read_vec4(&self, pnt: usize) -> [f32;4]{
+        [self.read_float(pnt), self.read_float(pnt + 1), self.read_float(pnt + 2), self.read_float(pnt + 3)]
+    }
+
+    fn data(&self, pnt: usize, len: usize) -> RomData<'_>{
+        RomData(&self.scene_rom[pnt..pnt + len])
+    }
+
+    /// Index just past the data of the record at `pnt`, whose length is stored at `pnt + 1`.
+    fn skip_record(&self, pnt: usize) -> usize{
+        pnt + 2 + self.scene_rom[pnt + 1] as usize
+    }
+
+    fn method<'a, F>(methods: &'a [CpuMethod<F>], id: i32, kind: &str) -> &'a CpuMethod<F>{
+        methods.get(id as usize - 1).unwrap_or_else(|| panic!("No {} method with id {} is registered on the CPU", kind, id))
+    }
+
+    fn animate(&self, kind: u32, pnt: usize) -> (Vec3, [f32;4]){
+        let mut translation = Vec3::default();
+        let mut rotation = [0.0,0.0,0.0,1.0];
+        let elapsed_time = self.elapsed_time;
+        if kind == (Animation::Oscillate{ amplitude: [0.0;3], frequency: 0.0, phase: 0.0 }).code(){
+            let frequency = self.read_float(pnt + 3);
+            let phase = self.read_float(pnt + 4);
+            translation = self.read_vec3(pnt) * (std::f32::consts::TAU * frequency * elapsed_time + phase).sin();
+        }
+        else if kind == (Animation::Spin{ axis: [0.0;3], speed: 0.0 }).code(){
+            let angle = self.read_float(pnt + 3) * elapsed_time;
+            let axis = self.read_vec3(pnt) * (angle * 0.5).sin();
+            rotation = [axis.x, axis.y, axis.z, (angle * 0.5).cos()];
+        }
+        else if kind == (Animation::Keyframes{ keys: Vec::new(), looping: false }).code(){
+            let count = self.scene_rom[pnt] as usize;
+            let keys = pnt + 2;
+            let first = self.read_float(keys);
+            let last = self.read_float(keys + (count - 1) * 4);
+            let mut t = elapsed_time;
+            if self.scene_rom[pnt + 1] != 0 && last > first {
+                t = first + (t - first).rem_euclid(last - first);
+            }
+
+            translation = self.read_vec3(keys + 1);
+            for i in 1..count.min(MAX_KEYFRAMES){
+                let key = keys + i * 4;
+                let key_time = self.read_float(key);
+                if t < key_time {
+                    let previous_time = self.read_float(key - 4);
+                    let f = ((t - previous_time) / (key_time - previous_time).max(0.0001)).clamp(0.0, 1.0);
+                    translation = self.read_vec3(key - 3).mix(self.read_vec3(key + 1), f);
+                    break;
+                }
+                translation = self.read_vec3(key + 1);
+            }
+        }
+        (translation, rotation)
+    }
+
+    fn apply_domain(&self, kind: u32, pnt: usize, position: &mut Vec3) -> f32{
+        let repeat = |position: Vec3, period: Vec3, limit: Option<Vec3>|{
+            let cell = (position / period.max(Vec3::splat(0.0001))).map(f32::round);
+            let cell = match limit{
+                Some(limit) => cell.max(-limit).min(limit),
+                None => cell,
+            };
+            let repeated = position - period * cell;
+            vec3(
+                if period.x >= 0.0001 { repeated.x } else { position.x },
+                if period.y >= 0.0001 { repeated.y } else { position.y },
+                if period.z >= 0.0001 { repeated.z } else { position.z })
+        };
+
+        let p = *position;
+        match kind{
+            x if x == (DomainModifier::Repeat{ period: [0.0;3] }).code() => {
+                *position = repeat(p, self.read_vec3(pnt), None);
+                1.0
+            },
+            x if x == (DomainModifier::RepeatLimited{ period: [0.0;3], limit: [0.0;3] }).code() => {
+                *position = repeat(p, self.read_vec3(pnt), Some(self.read_vec3(pnt + 3)));
+                1.0
+            },
+            x if x == (DomainModifier::Mirror{ axes: [false;3] }).code() => {
+                *position = p + (p.abs() - p) * self.read_vec3(pnt);
+                1.0
+            },
+            x if x == (DomainModifier::Twist{ strength: 0.0 }).code() => {
+                let k = self.read_float(pnt);
+                let (s, c) = (k * p.y).sin_cos();
+                *position = vec3(c * p.x + s * p.z, p.y, -s * p.x + c * p.z);
+                1.0 / (1.0 + k * k * position.xz().dot(position.xz())).sqrt()
+            },
+            x if x == (DomainModifier::Bend{ strength: 0.0 }).code() => {
+                let k = self.read_float(pnt);
+                let (s, c) = (k * p.x).sin_cos();
+                *position = vec3(c * p.x + s * p.y, -s * p.x + c * p.y, p.z);
+                1.0 / (1.0 + k * k * (position.x * position.x + position.y * position.y)).sqrt()
+            },
+            _ => 1.0,
+        }
+    }
+
+    /// Reads the bvh node record at `pnt` and moves `pnt` past it, returning its skip, instance count and box.
+    fn read_bvh_node(&self, pnt: &mut usize) -> (usize, usize, Vec3, Vec3){
+        let node = (self.scene_rom[*pnt] as usize, self.scene_rom[*pnt + 1] as usize, self.read_vec3(*pnt + 2), self.read_vec3(*pnt + 5));
+        *pnt += 8;
+        node
+    }
+
+    /// Reads the transform or animation record at `pnt` and moves `pnt` past it.
+    fn read_space(&self, op: i32, pnt: &mut usize) -> LocalSpace{
+        let inverse = |q: [f32;4]| [-q[0], -q[1], -q[2], q[3]];
+        if op == OP_TRANSFORM {
+            let space = LocalSpace{
+                translation: self.read_vec3(*pnt),
+                inverse_rotation: inverse(self.read_vec4(*pnt + 3)),
+                scale: self.read_vec3(*pnt + 7)
+            };
+            *pnt += 10;
+            space
+        }
+        else{
+            let (translation, rotation) = self.animate(self.scene_rom[*pnt], *pnt + 2);
+            *pnt = self.skip_record(*pnt);
+            LocalSpace{
+                translation,
+                inverse_rotation: inverse(rotation),
+                scale: Vec3::splat(1.0)
+            }
+        }
+    }
+
+    /// Evaluates every bound once for the ray, returns the distance at which the nearest instance can be entered
+    /// and the world space interval of every instance.
+    fn prepare_bounds(&self, world_origin: Vec3, world_ray: Vec3) -> (f32, Vec<Interval>){
+        let rom = &self.scene_rom;
+        let mut pnt = 0;
+        let mut intervals = Vec::new();
+        let mut nearest = BOUND_FAR;
+
+        let mut stack = vec![BoundState{
+            origin: world_origin,
+            ray: world_ray,
+            ray_scale: 1.0,
+            bounded: true,
+            blended: false
+        }];
+        let mut cur = stack[0];
+
+        loop{
+            let bound_type = rom[pnt] as i32;
+            pnt += 1;
+
+            if bound_type == OP_BVH_NODE {
+                let (skip, count, box_min, box_max) = self.read_bvh_node(&mut pnt);
+                if !interval_hit(bound_ray_box(cur.origin, cur.ray, (box_min + box_max) * 0.5, (box_max - box_min) * 0.5)) {
+                    intervals.extend(std::iter::repeat_n(BOUND_MISS, count));
+                    pnt += skip;
+                }
+                continue;
+            }
+
+            if bound_type == OP_TRANSFORM || bound_type == OP_ANIMATE {
+                let space = self.read_space(bound_type, &mut pnt);
+                cur.origin = space.point(cur.origin);
+                let local_ray = space.direction(cur.ray);
+                cur.ray_scale *= local_ray.length();
+                cur.ray = local_ray.normalize();
+                continue;
+            }
+
+            if bound_type == OP_DOMAIN {
+                pnt = self.skip_record(pnt);
+                cur.bounded = false;
+                continue;
+            }
+
+            if bound_type == OP_GROUP_BEGIN {
+                cur.blended = cur.blended || rom[pnt] >= CsgOp::SmoothUnion(0.0).code();
+                stack.push(cur);
+                pnt += 2;
+                continue;
+            }
+
+            if bound_type != OP_GROUP_END {
+                let mut interval = (0.0, BOUND_FAR);
+                if bound_type != 0 {
+                    let bound = Self::method(&self.methods.bound, bound_type, "bound");
+                    interval = (bound.method)(cur.origin, cur.ray, self.data(pnt, bound.data_len));
+                    pnt += bound.data_len;
+                }
+
+                let sdf_type = rom[pnt] as i32;
+                pnt += 1;
+                if sdf_type == 0 {
+                    break;
+                }
+                pnt += Self::method(&self.methods.sdf, sdf_type, "sdf").data_len;
+                pnt += 1 + rom[pnt] as usize;
+
+                let interval = if cur.bounded { (interval.0 / cur.ray_scale, interval.1 / cur.ray_scale) } else { (0.0, BOUND_FAR) };
+                intervals.push(interval);
+                if interval_hit(interval) {
+                    nearest = nearest.min(if cur.blended { 0.0 } else { interval.0 });
+                }
+            }
+            else{
+                stack.pop();
+            }
+
+            cur = *stack.last().unwrap();
+        }
+
+        (nearest, intervals)
+    }
+
+    fn sdf_scene(&self, world_origin: Vec3, world_position: Vec3, world_ray: Vec3, intervals: &[Interval]) -> HitInfo{
+        let rom = &self.scene_rom;
+        let mut pnt = 0;
+        let mut instance = 0;
+        let t = (world_position - world_origin).dot(world_ray);
+
+        // Group 0 is the implicit union of the whole scene
+        let mut groups = vec![GroupState{
+            hit: HitInfo::miss(),
+            op: CsgOp::Union.code() as i32,
+            k: 0.0,
+            empty: true,
+            origin: world_origin,
+            position: world_position,
+            ray: world_ray,
+            scale: 1.0
+        }];
+
+        let mut origin = world_origin;
+        let mut position = world_position;
+        let mut ray = world_ray;
+        let mut dist_scale = 1.0;
+
+        let mut running = true;
+        while running{
+            let bound_type = rom[pnt] as i32;
+            pnt += 1;
+
+            if bound_type == OP_BVH_NODE {
+                let (skip, count, box_min, box_max) = self.read_bvh_node(&mut pnt);
+                // Same as the shader, boxes around the position are entered even when the union is negative
+                let outside = (box_min - position).max(position - box_max);
+                if !groups[0].empty && outside.max(Vec3::splat(0.0)).length() > groups[0].hit.dist.max(0.0) {
+                    instance += count;
+                    pnt += skip;
+                }
+                continue;
+            }
+
+            if bound_type == OP_TRANSFORM || bound_type == OP_ANIMATE {
+                let space = self.read_space(bound_type, &mut pnt);
+                origin = space.point(origin);
+                position = space.point(position);
+                ray = space.direction(ray).normalize();
+                dist_scale *= space.distance_scale();
+                continue;
+            }
+
+            if bound_type == OP_DOMAIN {
+                dist_scale *= self.apply_domain(rom[pnt], pnt + 2, &mut position);
+                pnt = self.skip_record(pnt);
+                continue;
+            }
+
+            if bound_type == OP_GROUP_BEGIN {
+                groups.push(GroupState{
+                    hit: HitInfo::miss(),
+                    op: rom[pnt] as i32,
+                    k: self.read_float(pnt + 1),
+                    empty: true,
+                    origin,
+                    position,
+                    ray,
+                    scale: dist_scale
+                });
+                pnt += 2;
+                continue;
+            }
+
+            let mut hit = HitInfo::miss();
+
+            if bound_type == OP_GROUP_END {
+                hit = groups.pop().unwrap().hit;
+            }
+            else{
+                // Bounds were already evaluated by prepare_bounds
+                if bound_type != 0 {
+                    pnt += Self::method(&self.methods.bound, bound_type, "bound").data_len;
+                }
+                let interval = intervals.get(instance).copied().unwrap_or((0.0, BOUND_FAR));
+                instance += 1;
+
+                let top_level = groups.len() == 1;
+                let passed = top_level && t > interval.1;
+                let ahead = top_level && t < interval.0 && interval_hit(interval);
+                let hitable = interval_hit(interval) && !passed && !ahead;
+
+                let sdf_type = rom[pnt] as i32;
+                pnt += 1;
+                if sdf_type == 0 {
+                    running = false;
+                }
+                else{
+                    let sdf = Self::method(&self.methods.sdf, sdf_type, "sdf");
+                    let data = self.data(pnt, sdf.data_len);
+                    pnt += sdf.data_len;
+
+                    let tex_pnt = pnt + 1;
+                    pnt += 1 + rom[pnt] as usize;
+
+                    hit.id = tex_pnt;
+                    if hitable {
+                        hit.dist = (sdf.method)(position, data);
+                    }
+                }
+
+                hit.dist = if ahead { (interval.0 - t).max(HIT_DISTANCE) } else { hit.dist * dist_scale };
+            }
+
+            let group = groups.last_mut().unwrap();
+            origin = group.origin;
+            position = group.position;
+            ray = group.ray;
+            dist_scale = group.scale;
+
+            if running {
+                group.hit = if group.empty { hit } else { csg(group.hit, hit, group.op, group.k) };
+                group.empty = false;
+            }
+        }
+
+        groups[0].hit
+    }
+
+    fn material(&self, pnt: usize, position: Vec3, normal: Vec3) -> Material{
+        let missing = Material::diffuse([1.0,0.0,1.0]);
+        match self.scene_rom[pnt] as i32{
+            0 => missing,
+            id => match self.methods.tex.get(id as usize - 1){
+                Some(tex) => (tex.method)(position, normal, self.data(pnt + 1, tex.data_len)),
+                None => missing,
+            },
+        }
+    }
+
+    /// `side` is 1.0 when marching through empty space and -1.0 when marching inside a solid
+    fn march(&self, origin: Vec3, ray: Vec3, side: f32) -> MarchInfo{
+        let (mut traveled, intervals) = self.prepare_bounds(origin, ray);
+        let mut hit_position = origin + ray * traveled;
+        let mut cur = HitInfo::miss();
+        if traveled > MAX_DISTANCE {
+            return MarchInfo{ hit: cur, position: hit_position, intervals };
+        }
+        for _ in 0..256{
+            cur = self.sdf_scene(origin, hit_position, ray, &intervals);
+            cur.dist *= side;
+            traveled += cur.dist;
+            hit_position = origin + ray * traveled;
+            if cur.dist < HIT_DISTANCE || traveled > MAX_DISTANCE {
+                break;
+            }
+        }
+        MarchInfo{ hit: cur, position: hit_position, intervals }
+    }
+
+    fn normal(&self, origin: Vec3, position: Vec3, ray: Vec3, intervals: &[Interval]) -> Vec3{
+        let h = 0.001;
+        let sample = |k: Vec3| k * self.sdf_scene(origin, position + k * h, ray, intervals).dist;
+        (sample(vec3(1.0,-1.0,-1.0)) + sample(vec3(-1.0,-1.0,1.0)) + sample(vec3(-1.0,1.0,-1.0)) + sample(vec3(1.0,1.0,1.0))).normalize()
+    }
+
+    fn light_direction(&self) -> Vec3{
+        self.background.light_direction().unwrap_or(DEFAULT_LIGHT_DIRECTION).into()
+    }
+
+    /// Direct lighting of a surface, reflections and refractions are traced in `trace`
+    fn shade(&self, material: &Material, n: Vec3, ray: Vec3) -> Vec3{
+        let light_direction = self.light_direction();
+        let diffuse = n.dot(light_direction).max(0.0);
+        let half_vector = (light_direction - ray).normalize();
+        let shininess = 256.0 + (4.0 - 256.0) * material.roughness;
+        let specular = n.dot(half_vector).max(0.0).powf(shininess) * (1.0 - material.roughness);
+        let albedo = Vec3::from(material.albedo);
+        let specular_color = Vec3::splat(1.0).mix(albedo, material.metallic);
+
+        albedo * (1.0 - material.metallic) * (AMBIENT + (1.0 - AMBIENT) * diffuse) + specular_color * specular
+    }
+
+    /// The body of the scene shader's `main`, for a primary ray from the camera.
+    fn trace(&self, mut ray: Vec3) -> Vec3{
+        let mut origin = Vec3::from(self.position);
+        let mut side = 1.0;
+
+        let mut throughput = Vec3::splat(1.0);
+        let mut result = Vec3::default();
+
+        for bounce in 0..=self.bounce_depth{
+            let cur = self.march(origin, ray, side);
+
+            if cur.hit.dist >= HIT_DISTANCE {
+                result = result + throughput * self.background.evaluate(ray);
+                break;
+            }
+
+            // Fog only fills the space between solids
+            if side > 0.0 {
+                let fog = 1.0 - (-self.fog_density * origin.distance(cur.position)).exp();
+                result = result + throughput * self.background.evaluate(ray) * fog;
+                throughput = throughput * (1.0 - fog);
+            }
+
+            // Facing the incoming ray, so it points inwards while we are inside a solid
+            let n = self.normal(origin, cur.position, ray, &cur.intervals) * side;
+            let mat = self.material(cur.hit.id, cur.position, n * side);
+            let albedo = Vec3::from(mat.albedo);
+
+            let base = self.shade(&mat, n, ray);
+            result = result + throughput * Vec3::from(mat.emission);
+
+            let f0 = (1.0 - mat.ior) / (1.0 + mat.ior);
+            let f0 = f0 * f0 + (1.0 - f0 * f0) * mat.metallic;
+            let fresnel = f0 + (1.0 - f0) * (1.0 - (-ray).dot(n).clamp(0.0, 1.0)).powi(5);
+
+            let mut kr = fresnel * (1.0 - mat.roughness);
+            let mut kt = 0.0;
+            let mut refracted = Vec3::default();
+            if mat.opacity < 1.0 {
+                let eta = if side > 0.0 { 1.0 / mat.ior } else { mat.ior };
+                refracted = ray.refract(n, eta);
+                if refracted.dot(refracted) == 0.0 {
+                    // Total internal reflection
+                    kr = 1.0;
+                }
+                else{
+                    kr = kr.max(fresnel);
+                    kt = (1.0 - mat.opacity) * (1.0 - kr);
+                }
+            }
+            let kd = (1.0 - kr - kt).max(0.0);
+
+            if bounce == self.bounce_depth || (kr <= 0.0 && kt <= 0.0) {
+                result = result + throughput * base;
+                break;
+            }
+
+            // Only the dominant secondary ray is followed, the other one is shaded with the surface color
+            if kt > kr {
+                result = result + throughput * base * (kd + kr);
+                throughput = throughput * albedo * kt;
+                origin = cur.position - n * HIT_DISTANCE * 3.0;
+                ray = refracted;
+                side = -side;
+            }
+            else{
+                result = result + throughput * base * (kd + kt);
+                throughput = throughput * Vec3::splat(1.0).mix(albedo, mat.metallic) * kr;
+                origin = cur.position + n * HIT_DISTANCE * 3.0;
+                ray = ray.reflect(n);
+            }
+        }
+
+        result
+    }
+}
+
+fn quat_rotate(q: [f32;4], v: Vec3) -> Vec3{
+    let axis = vec3(q[0], q[1], q[2]);
+    v + axis.cross(axis.cross(v) + v * q[3]) * 2.0
+}
+
+/// Combines the next member b into the accumulated group a, subtraction keeps the material of a
+fn csg(a: HitInfo, b: HitInfo, op: i32, k: f32) -> HitInfo{
+    let mix = |x: f32, y: f32, t: f32| x + (y - x) * t;
+    match op as u32{
+        x if x == CsgOp::Subtract.code() => if -b.dist > a.dist { HitInfo{ dist: -b.dist, id: a.id } } else { a },
+        x if x == CsgOp::Intersect.code() => if b.dist > a.dist { b } else { a },
+        x if x == CsgOp::SmoothUnion(0.0).code() => {
+            let h = (0.5 + 0.5 * (b.dist - a.dist) / k).clamp(0.0, 1.0);
+            HitInfo{ dist: mix(b.dist, a.dist, h) - k * h * (1.0 - h), id: if h > 0.5 { a.id } else { b.id } }
+        },
+        x if x == CsgOp::SmoothSubtract(0.0).code() => {
+            let h = (0.5 - 0.5 * (a.dist + b.dist) / k).clamp(0.0, 1.0);
+            HitInfo{ dist: mix(a.dist, -b.dist, h) + k * h * (1.0 - h), id: a.id }
+        },
+        x if x == CsgOp::SmoothIntersect(0.0).code() => {
+            let h = (0.5 - 0.5 * (b.dist - a.dist) / k).clamp(0.0, 1.0);
+            HitInfo{ dist: mix(b.dist, a.dist, h) + k * h * (1.0 - h), id: if h > 0.5 { a.id } else { b.id } }
+        },
+        _ => if b.dist < a.dist { b } else { a },
+    }
+}
@@ -0,0 +1,151 @@
+//! Rust counterparts of the bound, sdf and tex methods registered with the `Renderer`.
+//!
+//! Ids are handed out in registration order like the `Renderer` does, so registering the same
+//! methods in the same order makes a rom readable by both.
+
+use std::sync::Arc;
+
+use crate::renderer::{math::Vec3, primitives::{Primitive, PrimitiveIds, PrimitiveLibrary}, scene::Material};
+
+use super::primitives::*;
+
+/// Rom words of a method, read the way the generated `DataEntry` code reads them.
+#[derive(Clone, Copy, Debug)]
+pub struct RomData<'a>(pub &'a [u32]);
+
+impl<'a> RomData<'a> {
+    pub fn float(&self, index: usize) -> f32{
+        f32::from_bits(self.0[index])
+    }
+
+    pub fn vec3(&self, index: usize) -> Vec3{
+        Vec3{
+            x: self.float(index),
+            y: self.float(index + 1),
+            z: self.float(index + 2)
+        }
+    }
+
+    pub fn int(&self, index: usize) -> i32{
+        self.0[index] as i32
+    }
+}
+
+/// `bound_*(origin, ray, data)`
+pub type BoundMethod = Arc<dyn Fn(Vec3, Vec3, RomData) -> Interval + Send + Sync>;
+/// `sdf_*(position, data)`
+pub type SdfMethod = Arc<dyn Fn(Vec3, RomData) -> f32 + Send + Sync>;
+/// `tex(position, normal, data)`, the normal is what the shader has in `hit_normal`.
+pub type TexMethod = Arc<dyn Fn(Vec3, Vec3, RomData) -> Material + Send + Sync>;
+
+/// A registered method and the number of rom words it reads.
+#[derive(Clone)]
+pub struct CpuMethod<F>{
+    pub data_len: usize,
+    pub method: F
+}
+
+#[derive(Clone, Default)]
+pub struct CpuMethods{
+    pub(crate) bound: Vec<CpuMethod<BoundMethod>>,
+    pub(crate) sdf: Vec<CpuMethod<SdfMethod>>,
+    pub(crate) tex: Vec<CpuMethod<TexMethod>>,
+}
+
+impl CpuMethods{
+    pub fn new() -> Self{
+        Self::default()
+    }
+
+    pub fn register_bound_method(&mut self, data_len: usize, method: impl Fn(Vec3, Vec3, RomData) -> Interval + Send + Sync + 'static) -> u32{
+        self.bound.push(CpuMethod{ data_len, method: Arc::new(method) });
+        self.bound.len() as u32
+    }
+
+    pub fn register_sdf_method(&mut self, data_len: usize, method: impl Fn(Vec3, RomData) -> f32 + Send + Sync + 'static) -> u32{
+        self.sdf.push(CpuMethod{ data_len, method: Arc::new(method) });
+        self.sdf.len() as u32
+    }
+
+    pub fn register_tex_method(&mut self, data_len: usize, method: impl Fn(Vec3, Vec3, RomData) -> Material + Send + Sync + 'static) -> u32{
+        self.tex.push(CpuMethod{ data_len, method: Arc::new(method) });
+        self.tex.len() as u32
+    }
+
+    /// `bound_sphere`, `sdf_sphere` and `color_sphere` from `sdf/sphere.glsl`, taking `center`, `radius` and a color.
+    pub fn register_sphere(&mut self) -> PrimitiveIds{
+        PrimitiveIds{
+            bound: self.register_bound_method(4, |o, r, d| bound_sphere(o, r, d.vec3(0), d.float(3))),
+            sdf: self.register_sdf_method(4, |p, d| sdf_sphere(p, d.vec3(0), d.float(3))),
+            tex: self.register_tex_method(3, |_, _, d| Material::diffuse(d.vec3(0).into())),
+        }
+    }
+
+    /// `bound_plane`, `sdf_plane` and `color_plane` from `sdf/plane.glsl`, taking `normal` and `height`.
+    pub fn register_plane(&mut self) -> PrimitiveIds{
+        PrimitiveIds{
+            bound: self.register_bound_method(4, |o, r, d| bound_plane(o, r, d.vec3(0), d.float(3))),
+            sdf: self.register_sdf_method(4, |p, d| sdf_plane(p, d.vec3(0), d.float(3))),
+            tex: self.register_tex_method(0, |_, _, _| Material::diffuse([1.0,1.0,0.0])),
+        }
+    }
+
+    /// `material_constant` from `sdf/material.glsl`, reading a whole `Material`.
+    pub fn register_material(&mut self) -> u32{
+        self.register_tex_method(10, |_, _, d| Material{
+            albedo: d.vec3(0).into(),
+            roughness: d.float(3),
+            metallic: d.float(4),
+            emission: d.vec3(5).into(),
+            opacity: d.float(8),
+            ior: d.float(9)
+        })
+    }
+
+    /// Same as `Renderer::register_primitive_library`.
+    pub fn register_primitive_library(&mut self) -> PrimitiveLibrary{
+        let ids = Primitive::ALL.iter().map(|primitive|{
+            let primitive = *primitive;
+            let len = primitive.param_len();
+            let ids = PrimitiveIds{
+                bound: self.register_bound_method(len, move |o, r, d| primitive_bound(primitive, o, r, d)),
+                sdf: self.register_sdf_method(len, move |p, d| primitive_sdf(primitive, p, d)),
+                tex: self.register_tex_method(3, |_, _, d| Material::diffuse(d.vec3(0).into())),
+            };
+            (primitive, ids)
+        }).collect();
+        PrimitiveLibrary{
+            ids
+        }
+    }
+}
+
+fn primitive_sdf(primitive: Primitive, p: Vec3, d: RomData) -> f32{
+    match primitive{
+        Primitive::Box => sdf_box(p, d.vec3(0), d.vec3(3)),
+        Primitive::RoundedBox => sdf_rounded_box(p, d.vec3(0), d.vec3(3), d.float(6)),
+        Primitive::Torus => sdf_torus(p, d.vec3(0), d.float(3), d.float(4)),
+        Primitive::Capsule => sdf_capsule(p, d.vec3(0), d.float(3), d.float(4)),
+        Primitive::Cylinder => sdf_cylinder(p, d.vec3(0), d.float(3), d.float(4)),
+        Primitive::Cone => sdf_cone(p, d.vec3(0), d.float(3), d.float(4)),
+        Primitive::Ellipsoid => sdf_ellipsoid(p, d.vec3(0), d.vec3(3)),
+        Primitive::Octahedron => sdf_octahedron(p, d.vec3(0), d.float(3)),
+        Primitive::HexPrism => sdf_hex_prism(p, d.vec3(0), d.float(3), d.float(4)),
+        Primitive::LineSegment => sdf_segment(p, d.vec3(0), d.vec3(3), d.float(6)),
+    }
+}
+
+fn primitive_bound(primitive: Primitive, o: Vec3, r: Vec3, d: RomData) -> Interval{
+    match primitive{
+        Primitive::Box => bound_box(o, r, d.vec3(0), d.vec3(3)),
+        Primitive::RoundedBox => bound_rounded_box(o, r, d.vec3(0), d.vec3(3), d.float(6)),
+        Primitive::Torus => bound_torus(o, r, d.vec3(0), d.float(3), d.float(4)),
+        Primitive::Capsule => bound_capsule(o, r, d.vec3(0), d.float(3), d.float(4)),
+        Primitive::Cylinder => bound_cylinder(o, r, d.vec3(0), d.float(3), d.float(4)),
+        Primitive::Cone => bound_cone(o, r, d.vec3(0), d.float(3), d.float(4)),
+        Primitive::Ellipsoid => bound_ellipsoid(o, r, d.vec3(0), d.vec3(3)),
+        Primitive::Octahedron => bound_octahedron(o, r, d.vec3(0), d.float(3)),
+        Primitive::HexPrism => bound_hex_prism(o, r, d.vec3(0), d.float(3), d.float(4)),
+        Primitive::LineSegment => bound_segment(o, r, d.vec3(0), d.vec3(3), d.float(6)),
+    }
+}
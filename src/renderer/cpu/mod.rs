@@ -0,0 +1,7 @@
+//! CPU versions of the shader code, for checking the shipped methods without a GL context
+//! and for rendering scene roms on machines without one.
+
+pub mod primitives;
+pub mod methods;
+pub mod backend;
+pub mod png;
@@ -0,0 +1,166 @@
+//! Minimal PNG support for `CpuImage`s, enough to write renders and read the written files back.
+//!
+//! Rendered thumbnails are small, so the writer stores the pixels in uncompressed deflate blocks
+//! and the renderer stays free of extra dependencies.
+
+use std::{fs, io, path::Path};
+
+use super::backend::CpuImage;
+
+const SIGNATURE: [u8;8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+/// Largest payload of a stored deflate block.
+const MAX_STORED_BLOCK: usize = 0xffff;
+
+impl CpuImage{
+    /// Encodes the image as an 8 bit rgba PNG.
+    pub fn to_png(&self) -> Vec<u8>{
+        let mut header = Vec::with_capacity(13);
+        header.extend(self.width.to_be_bytes());
+        header.extend(self.height.to_be_bytes());
+        // Bit depth 8, color type rgba, default compression, filter and no interlacing
+        header.extend([8, 6, 0, 0, 0]);
+
+        // Every row starts with filter type 0, leaving the pixels as they are
+        let row_len = self.width as usize * 4;
+        let mut raw = Vec::with_capacity((row_len + 1) * self.height as usize);
+        for row in self.pixels.chunks(row_len.max(1)).take(self.height as usize){
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        let mut png = SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &header);
+        write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()>{
+        fs::write(path, self.to_png())
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8;4], data: &[u8]){
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8>{
+    // Deflate without a preset dictionary and the fastest compression level
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next(){
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32{
+    let mut crc = !0u32;
+    for byte in data{
+        crc ^= *byte as u32;
+        for _ in 0..8{
+            crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub(crate) fn adler32(data: &[u8]) -> u32{
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data{
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+impl CpuImage{
+    /// Decodes a PNG as written by `to_png`, 8 bit rgba without filters in stored deflate blocks.
+    /// Compressed PNGs from other tools are rejected, they have to be written back with `to_png` first.
+    pub fn from_png(png: &[u8]) -> Result<CpuImage, String>{
+        if !png.starts_with(&SIGNATURE) {
+            return Err("Not a PNG".into());
+        }
+
+        let mut header = None;
+        let mut compressed = Vec::new();
+        let mut pnt = SIGNATURE.len();
+        while pnt + 12 <= png.len(){
+            let len = u32::from_be_bytes(png[pnt..pnt + 4].try_into().unwrap()) as usize;
+            let kind = &png[pnt + 4..pnt + 8];
+            let data = png.get(pnt + 8..pnt + 8 + len).ok_or("Truncated chunk")?;
+            match kind{
+                b"IHDR" => header = Some(data),
+                b"IDAT" => compressed.extend_from_slice(data),
+                b"IEND" => break,
+                _ => {},
+            }
+            pnt += 12 + len;
+        }
+
+        let header = header.filter(|x| x.len() == 13).ok_or("Missing header")?;
+        let width = u32::from_be_bytes(header[0..4].try_into().unwrap());
+        let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        if header[8..] != [8, 6, 0, 0, 0] {
+            return Err("Only 8 bit rgba PNGs without interlacing are supported".into());
+        }
+
+        let raw = zlib_unstore(&compressed)?;
+        let row_len = width as usize * 4;
+        if raw.len() < (row_len + 1) * height as usize {
+            return Err("Not enough image data".into());
+        }
+
+        let mut pixels = Vec::with_capacity(row_len * height as usize);
+        for row in raw.chunks(row_len + 1).take(height as usize){
+            if row[0] != 0 {
+                return Err(format!("Unsupported filter type {}", row[0]));
+            }
+            pixels.extend_from_slice(&row[1..]);
+        }
+
+        Ok(CpuImage{
+            width,
+            height,
+            pixels
+        })
+    }
+}
+
+/// Reads back the stored blocks of `zlib_stored`.
+fn zlib_unstore(data: &[u8]) -> Result<Vec<u8>, String>{
+    if data.len() < 2 || data[0] & 0x0f != 8 || data[1] & 0x20 != 0 {
+        return Err("Unsupported zlib stream".into());
+    }
+
+    let mut out = Vec::new();
+    let mut pnt = 2;
+    loop{
+        let header = data.get(pnt..pnt + 5).ok_or("Truncated stored block")?;
+        if header[0] & 0b110 != 0 {
+            return Err("Only uncompressed deflate blocks are supported".into());
+        }
+        let len = u16::from_le_bytes([header[1], header[2]]) as usize;
+        let block = data.get(pnt + 5..pnt + 5 + len).ok_or("Truncated stored block")?;
+        out.extend_from_slice(block);
+        pnt += 5 + len;
+        if header[0] & 1 == 1 {
+            break;
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,186 @@
+//! Ports of the shipped sdf and bound methods in `sdf/`, kept line by line with the GLSL.
+
+use crate::renderer::{HIT_DISTANCE, math::{Vec3, Vec2, vec3, vec2}};
+
+/// `[tmin, tmax]` along a ray, see `sdf/bounds.glsl` for the contract bound methods follow.
+pub type Interval = (f32, f32);
+
+pub const BOUND_FAR: f32 = 1e30;
+pub const BOUND_MISS: Interval = (BOUND_FAR, -BOUND_FAR);
+pub const BOUND_MARGIN: f32 = 2.0 * HIT_DISTANCE;
+
+pub fn interval_hit(interval: Interval) -> bool{
+    interval.0 <= interval.1
+}
+
+pub fn bound_ray_sphere(origin: Vec3, ray: Vec3, center: Vec3, radius: f32) -> Interval{
+    let l = origin - center;
+    let r = radius + BOUND_MARGIN;
+    let b = l.dot(ray);
+    let disc = b * b - (l.dot(l) - r * r);
+    if disc < 0.0 {
+        return BOUND_MISS;
+    }
+
+    let s = disc.sqrt();
+    ((-b - s).max(0.0), -b + s)
+}
+
+pub fn bound_ray_box(origin: Vec3, ray: Vec3, center: Vec3, half_size: Vec3) -> Interval{
+    let safe_ray = ray.map(|x| if x.abs() < 0.00000001 { 0.00000001 } else { x });
+    let size = half_size + BOUND_MARGIN;
+    let t0 = (center - size - origin) / safe_ray;
+    let t1 = (center + size - origin) / safe_ray;
+    let near = t0.min(t1);
+    let far = t0.max(t1);
+    (near.max_element().max(0.0), far.min_element())
+}
+
+pub fn sdf_sphere(position: Vec3, center: Vec3, radius: f32) -> f32{
+    (position - center).length() - radius
+}
+
+pub fn bound_sphere(origin: Vec3, ray: Vec3, center: Vec3, radius: f32) -> Interval{
+    bound_ray_sphere(origin, ray, center, radius)
+}
+
+pub fn sdf_plane(position: Vec3, normal: Vec3, height: f32) -> f32{
+    position.dot(normal) - height
+}
+
+pub fn bound_plane(origin: Vec3, ray: Vec3, normal: Vec3, height: f32) -> Interval{
+    let start = sdf_plane(origin, normal, height) - BOUND_MARGIN;
+    let slope = ray.dot(normal);
+    if start <= 0.0 {
+        return (0.0, if slope > 0.0 { -start / slope } else { BOUND_FAR });
+    }
+    if slope >= 0.0 {
+        return BOUND_MISS;
+    }
+    (-start / slope, BOUND_FAR)
+}
+
+pub fn sdf_box(position: Vec3, center: Vec3, half_size: Vec3) -> f32{
+    let q = (position - center).abs() - half_size;
+    q.max(Vec3::splat(0.0)).length() + q.max_element().min(0.0)
+}
+
+pub fn bound_box(origin: Vec3, ray: Vec3, center: Vec3, half_size: Vec3) -> Interval{
+    bound_ray_box(origin, ray, center, half_size)
+}
+
+pub fn sdf_rounded_box(position: Vec3, center: Vec3, half_size: Vec3, rounding: f32) -> f32{
+    let q = (position - center).abs() - half_size + rounding;
+    q.max(Vec3::splat(0.0)).length() + q.max_element().min(0.0) - rounding
+}
+
+pub fn bound_rounded_box(origin: Vec3, ray: Vec3, center: Vec3, half_size: Vec3, _rounding: f32) -> Interval{
+    bound_ray_box(origin, ray, center, half_size)
+}
+
+pub fn sdf_torus(position: Vec3, center: Vec3, major_radius: f32, minor_radius: f32) -> f32{
+    let p = position - center;
+    let q = vec2(p.xz().length() - major_radius, p.y);
+    q.length() - minor_radius
+}
+
+pub fn bound_torus(origin: Vec3, ray: Vec3, center: Vec3, major_radius: f32, minor_radius: f32) -> Interval{
+    let outer = major_radius + minor_radius;
+    bound_ray_box(origin, ray, center, vec3(outer, minor_radius, outer))
+}
+
+pub fn sdf_capsule(position: Vec3, center: Vec3, half_height: f32, radius: f32) -> f32{
+    let mut p = position - center;
+    p.y -= p.y.clamp(-half_height, half_height);
+    p.length() - radius
+}
+
+pub fn bound_capsule(origin: Vec3, ray: Vec3, center: Vec3, half_height: f32, radius: f32) -> Interval{
+    bound_ray_box(origin, ray, center, vec3(radius, half_height + radius, radius))
+}
+
+pub fn sdf_cylinder(position: Vec3, center: Vec3, half_height: f32, radius: f32) -> f32{
+    let p = position - center;
+    let d = vec2(p.xz().length(), p.y).abs() - vec2(radius, half_height);
+    d.x.max(d.y).min(0.0) + d.max(Vec2::default()).length()
+}
+
+pub fn bound_cylinder(origin: Vec3, ray: Vec3, center: Vec3, half_height: f32, radius: f32) -> Interval{
+    bound_ray_box(origin, ray, center, vec3(radius, half_height, radius))
+}
+
+pub fn sdf_cone(position: Vec3, center: Vec3, half_height: f32, radius: f32) -> f32{
+    let p = position - center;
+    let q = vec2(p.xz().length(), p.y);
+    let k1 = vec2(0.0, half_height);
+    let k2 = vec2(-radius, 2.0 * half_height);
+    let ca = vec2(q.x - q.x.min(if q.y < 0.0 { radius } else { 0.0 }), q.y.abs() - half_height);
+    let cb = q - k1 + k2 * ((k1 - q).dot(k2) / k2.dot(k2)).clamp(0.0, 1.0);
+    let s = if cb.x < 0.0 && ca.y < 0.0 { -1.0 } else { 1.0 };
+    s * ca.dot(ca).min(cb.dot(cb)).sqrt()
+}
+
+pub fn bound_cone(origin: Vec3, ray: Vec3, center: Vec3, half_height: f32, radius: f32) -> Interval{
+    bound_ray_box(origin, ray, center, vec3(radius, half_height, radius))
+}
+
+pub fn sdf_ellipsoid(position: Vec3, center: Vec3, radii: Vec3) -> f32{
+    let p = position - center;
+    ((p / radii).length() - 1.0) * radii.min_element()
+}
+
+pub fn bound_ellipsoid(origin: Vec3, ray: Vec3, center: Vec3, radii: Vec3) -> Interval{
+    bound_ray_box(origin, ray, center, radii * (1.0 + HIT_DISTANCE / radii.min_element()))
+}
+
+pub fn sdf_octahedron(position: Vec3, center: Vec3, size: f32) -> f32{
+    let p = (position - center).abs();
+    (p.x + p.y + p.z - size) * 0.57735027
+}
+
+pub fn bound_octahedron(origin: Vec3, ray: Vec3, center: Vec3, size: f32) -> Interval{
+    bound_ray_sphere(origin, ray, center, size + HIT_DISTANCE)
+}
+
+pub fn sdf_hex_prism(position: Vec3, center: Vec3, radius: f32, half_height: f32) -> f32{
+    let k = vec3(-0.8660254, 0.5, 0.57735);
+    let a = (position - center).abs();
+    let mut p = vec3(a.x, a.z, a.y);
+    let kxy = vec2(k.x, k.y);
+    let fold = kxy * (2.0 * kxy.dot(vec2(p.x, p.y)).min(0.0));
+    p.x -= fold.x;
+    p.y -= fold.y;
+    let d = vec2(
+        (vec2(p.x, p.y) - vec2(p.x.clamp(-k.z * radius, k.z * radius), radius)).length() * sign(p.y - radius),
+        p.z - half_height);
+    d.x.max(d.y).min(0.0) + d.max(Vec2::default()).length()
+}
+
+pub fn bound_hex_prism(origin: Vec3, ray: Vec3, center: Vec3, radius: f32, half_height: f32) -> Interval{
+    let outer = radius * 1.1547005;
+    bound_ray_box(origin, ray, center, vec3(outer, half_height, outer))
+}
+
+pub fn sdf_segment(position: Vec3, start: Vec3, end: Vec3, radius: f32) -> f32{
+    let pa = position - start;
+    let ba = end - start;
+    let h = (pa.dot(ba) / ba.dot(ba)).clamp(0.0, 1.0);
+    (pa - ba * h).length() - radius
+}
+
+pub fn bound_segment(origin: Vec3, ray: Vec3, start: Vec3, end: Vec3, radius: f32) -> Interval{
+    bound_ray_box(origin, ray, (start + end) * 0.5, (end - start).abs() * 0.5 + radius)
+}
+
+/// GLSL `sign`, which unlike `f32::signum` is 0 at 0.
+fn sign(x: f32) -> f32{
+    if x > 0.0 {
+        1.0
+    }
+    else if x < 0.0 {
+        -1.0
+    }
+    else {
+        0.0
+    }
+}
@@ -0,0 +1,184 @@
+//! Vector math shared by the CPU backend and the CPU side of the renderer, like bounding boxes and backgrounds.
+
+use std::ops::{Add, Sub, Mul, Div, Neg};
+
+/// Minimal vector types mirroring the GLSL ones, so shader methods can be ported line by line.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Vec3{
+    pub x: f32,
+    pub y: f32,
+    pub z: f32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Vec2{
+    pub x: f32,
+    pub y: f32
+}
+
+pub fn vec3(x: f32, y: f32, z: f32) -> Vec3{
+    Vec3{ x, y, z }
+}
+
+pub fn vec2(x: f32, y: f32) -> Vec2{
+    Vec2{ x, y }
+}
+
+impl Vec3{
+    pub fn splat(v: f32) -> Self{
+        vec3(v, v, v)
+    }
+
+    pub fn dot(self, other: Vec3) -> f32{
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3{
+        vec3(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x)
+    }
+
+    pub fn length(self) -> f32{
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Vec3{
+        self / self.length()
+    }
+
+    pub fn abs(self) -> Vec3{
+        self.map(f32::abs)
+    }
+
+    pub fn max(self, other: Vec3) -> Vec3{
+        vec3(self.x.max(other.x), self.y.max(other.y), self.z.max(other.z))
+    }
+
+    pub fn min(self, other: Vec3) -> Vec3{
+        vec3(self.x.min(other.x), self.y.min(other.y), self.z.min(other.z))
+    }
+
+    pub fn max_element(self) -> f32{
+        self.x.max(self.y).max(self.z)
+    }
+
+    pub fn min_element(self) -> f32{
+        self.x.min(self.y).min(self.z)
+    }
+
+    pub fn map(self, f: impl Fn(f32) -> f32) -> Vec3{
+        vec3(f(self.x), f(self.y), f(self.z))
+    }
+
+    pub fn xz(self) -> Vec2{
+        vec2(self.x, self.z)
+    }
+
+    pub fn distance(self, other: Vec3) -> f32{
+        (self - other).length()
+    }
+
+    /// GLSL `mix`.
+    pub fn mix(self, other: Vec3, t: f32) -> Vec3{
+        self + (other - self) * t
+    }
+
+    /// GLSL `reflect`, `self` is the incoming direction.
+    pub fn reflect(self, normal: Vec3) -> Vec3{
+        self - normal * (2.0 * normal.dot(self))
+    }
+
+    /// GLSL `refract`, the zero vector on total internal reflection.
+    pub fn refract(self, normal: Vec3, eta: f32) -> Vec3{
+        let d = normal.dot(self);
+        let k = 1.0 - eta * eta * (1.0 - d * d);
+        if k < 0.0 {
+            Vec3::default()
+        }
+        else{
+            self * eta - normal * (eta * d + k.sqrt())
+        }
+    }
+}
+
+impl Vec2{
+    pub fn dot(self, other: Vec2) -> f32{
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn length(self) -> f32{
+        self.dot(self).sqrt()
+    }
+
+    pub fn abs(self) -> Vec2{
+        vec2(self.x.abs(), self.y.abs())
+    }
+
+    pub fn max(self, other: Vec2) -> Vec2{
+        vec2(self.x.max(other.x), self.y.max(other.y))
+    }
+}
+
+impl From<[f32;3]> for Vec3{
+    fn from(v: [f32;3]) -> Self {
+        vec3(v[0], v[1], v[2])
+    }
+}
+
+/// GLSL `smoothstep`.
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32{
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl From<Vec3> for [f32;3]{
+    fn from(v: Vec3) -> Self {
+        [v.x, v.y, v.z]
+    }
+}
+
+macro_rules! vector_ops {
+    ($t:ident, $($field:ident),+) => {
+        impl Add for $t{
+            type Output = $t;
+            fn add(self, rhs: $t) -> $t { $t{ $($field: self.$field + rhs.$field),+ } }
+        }
+        impl Sub for $t{
+            type Output = $t;
+            fn sub(self, rhs: $t) -> $t { $t{ $($field: self.$field - rhs.$field),+ } }
+        }
+        impl Mul for $t{
+            type Output = $t;
+            fn mul(self, rhs: $t) -> $t { $t{ $($field: self.$field * rhs.$field),+ } }
+        }
+        impl Div for $t{
+            type Output = $t;
+            fn div(self, rhs: $t) -> $t { $t{ $($field: self.$field / rhs.$field),+ } }
+        }
+        impl Add<f32> for $t{
+            type Output = $t;
+            fn add(self, rhs: f32) -> $t { $t{ $($field: self.$field + rhs),+ } }
+        }
+        impl Sub<f32> for $t{
+            type Output = $t;
+            fn sub(self, rhs: f32) -> $t { $t{ $($field: self.$field - rhs),+ } }
+        }
+        impl Mul<f32> for $t{
+            type Output = $t;
+            fn mul(self, rhs: f32) -> $t { $t{ $($field: self.$field * rhs),+ } }
+        }
+        impl Div<f32> for $t{
+            type Output = $t;
+            fn div(self, rhs: f32) -> $t { $t{ $($field: self.$field / rhs),+ } }
+        }
+        impl Neg for $t{
+            type Output = $t;
+            fn neg(self) -> $t { $t{ $($field: -self.$field),+ } }
+        }
+    };
+}
+
+vector_ops!(Vec3, x, y, z);
+vector_ops!(Vec2, x, y);
@@ -1,4 +1,4 @@
-use std::{path::PathBuf, fmt::format};
+use std::{path::PathBuf, fmt};
 
 use miniquad::UniformType;
 
@@ -12,8 +12,29 @@ pub struct DataEntry{
     pub type_: UniformType
 }
 
-impl ToString for DataEntry{
-    fn to_string(&self) -> String {
+impl DataEntry{
+    /// Entry holding the slot of a texture from `Renderer::register_texture`, to pass to `sample_texture`.
+    pub fn texture_slot(name: &str) -> Self{
+        Self{
+            name: name.into(),
+            type_: UniformType::Int1
+        }
+    }
+
+    /// Number of rom words the entry takes up.
+    pub fn data_len(&self) -> usize{
+        match self.type_{
+            UniformType::Float1 | UniformType::Int1 => 1,
+            UniformType::Float2 | UniformType::Int2 => 2,
+            UniformType::Float3 | UniformType::Int3 => 3,
+            UniformType::Float4 | UniformType::Int4 => 4,
+            UniformType::Mat4 => 16,
+        }
+    }
+}
+
+impl fmt::Display for DataEntry{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let (init,len,type_) = match self.type_{
             UniformType::Float1 => ("intBitsToFloat(scene_rom[pnt])",1,"float"),
             UniformType::Float2 => ("vec2(intBitsToFloat(scene_rom[pnt]),intBitsToFloat(scene_rom[pnt+1]))",2,"vec2"),
@@ -45,7 +66,25 @@ impl ToString for DataEntry{
             UniformType::Mat4 => unimplemented!(),
         };
 
-        format!("{} {} = {}; pnt += {};",type_,self.name,init,len)
+        write!(f, "{} {} = {}; pnt += {};",type_,self.name,init,len)
+    }
+}
+
+/// Values the generated code passes to a method ahead of its rom data, in the order they were added.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImplicitParam{
+    /// `float elapsed_time`, seconds since the renderer started.
+    ElapsedTime,
+    /// `int frame_index`, counting up by one every drawn frame.
+    FrameIndex,
+}
+
+impl ImplicitParam{
+    pub fn name(&self) -> &'static str{
+        match self{
+            ImplicitParam::ElapsedTime => "elapsed_time",
+            ImplicitParam::FrameIndex => "frame_index",
+        }
     }
 }
 
@@ -53,15 +92,57 @@ pub struct DataDeserializer{
     pub entries: Vec<DataEntry>
 }
 impl DataDeserializer{
+    pub fn new(entries: Vec<DataEntry>) -> Self{
+        Self{
+            entries
+        }
+    }
+
+    /// Arguments of the generated call, the given leading ones followed by the implicit parameters and the entries.
+    fn argument_names(&self, leading: &[&str], implicit: &[ImplicitParam]) -> String{
+        leading.iter().map(|x| x.to_string())
+            .chain(implicit.iter().map(|x| x.name().to_string()))
+            .chain(self.entries.iter().map(|x| x.name.clone()))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Deserializer matching the layout written by `scene::Material`.
+    pub fn material() -> Self{
+        Self::new(vec![
+            DataEntry{ name: "albedo".into(), type_: UniformType::Float3 },
+            DataEntry{ name: "roughness".into(), type_: UniformType::Float1 },
+            DataEntry{ name: "metallic".into(), type_: UniformType::Float1 },
+            DataEntry{ name: "emission".into(), type_: UniformType::Float3 },
+            DataEntry{ name: "opacity".into(), type_: UniformType::Float1 },
+            DataEntry{ name: "ior".into(), type_: UniformType::Float1 },
+        ])
+    }
+
+    /// Number of rom words the entries take up.
+    pub fn data_len(&self) -> usize{
+        self.entries.iter().map(|x| x.data_len()).sum()
+    }
+
+    /// Case stepping over the data of the method, for passes that don't need to evaluate it.
+    pub fn create_skip_case(&self, id: u32) -> String{
+        format!("case {}: pnt += {}; break;", id, self.data_len())
+    }
+
     pub fn create_bounding_case(&self, id: u32, name: &str) -> String{
+        self.create_bounding_case_with_implicit(id, name, &[])
+    }
+
+    /// Same as `create_bounding_case`, the method also gets the implicit parameters.
+    pub(super) fn create_bounding_case_with_implicit(&self, id: u32, name: &str, implicit: &[ImplicitParam]) -> String{
         let values = self.entries.iter().map(|x|x.to_string()).collect::<Vec<_>>().join("\n");
-        let value_names = ["origin".to_string(), "ray".to_string()].into_iter().chain(self.entries.iter().map(|x|x.name.clone())).collect::<Vec<String>>().join(", ");
+        let value_names = self.argument_names(&["origin", "ray"], implicit);
         format!(
             "
             case {}: {{
                 {}
 
-                hitable = {}({});
+                interval = as_interval({}({}));
             }} break;
             ",
             id,
@@ -72,8 +153,13 @@ impl DataDeserializer{
     }
 
     pub fn create_sdf_case(&self, id: u32, name: &str) -> String{
+        self.create_sdf_case_with_implicit(id, name, &[])
+    }
+
+    /// Same as `create_sdf_case`, the method also gets the implicit parameters.
+    pub(super) fn create_sdf_case_with_implicit(&self, id: u32, name: &str, implicit: &[ImplicitParam]) -> String{
         let values = self.entries.iter().map(|x|x.to_string()).collect::<Vec<_>>().join("\n");
-        let value_names = ["position".to_string()].into_iter().chain(self.entries.iter().map(|x|x.name.clone())).collect::<Vec<String>>().join(", ");
+        let value_names = self.argument_names(&["position"], implicit);
         format!(
             "
             case {}: {{
@@ -82,11 +168,9 @@ impl DataDeserializer{
                 int tex_pnt = pnt+1;
                 pnt += 1 + scene_rom[pnt];
 
+                hit.id = tex_pnt;
                 if (hitable){{
-                    float new = {}({});
-                    if (new < curHit.dist){{ 
-                        curHit = HitInfo(new,tex_pnt);
-                    }}
+                    hit.dist = {}({});
                 }}
             }} break;
             ",
@@ -98,13 +182,18 @@ impl DataDeserializer{
     }
 
     pub fn create_tex_case(&self, id: u32, name: &str) -> String{
+        self.create_tex_case_with_implicit(id, name, &[])
+    }
+
+    /// Same as `create_tex_case`, the method also gets the implicit parameters.
+    pub(super) fn create_tex_case_with_implicit(&self, id: u32, name: &str, implicit: &[ImplicitParam]) -> String{
         let values = self.entries.iter().map(|x|x.to_string()).collect::<Vec<_>>().join("\n");
-        let value_names = ["position".to_string()].into_iter().chain(self.entries.iter().map(|x|x.name.clone())).collect::<Vec<String>>().join(", ");
+        let value_names = self.argument_names(&["position"], implicit);
         
         format!(
             "case {}:{{
                 {}
-                return {}({}); 
+                return as_material({}({})); 
             }} break;",
             id,
             values,
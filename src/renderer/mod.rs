@@ -3,48 +3,96 @@ use std::{fs, mem::MaybeUninit, time::Instant};
 use miniquad::{Context, EventHandler, PassAction};
 
 
-use crate::renderer::scene::SceneSerializer;
+use crate::renderer::scene::{SceneSerializer, CsgOp, DomainModifier, Animation, OP_GROUP_BEGIN, OP_GROUP_END, OP_TRANSFORM, OP_DOMAIN, OP_ANIMATE, OP_BVH_NODE, MAX_GROUP_DEPTH, MAX_KEYFRAMES};
 
-use self::{methods::{MethodDefinition, DataDeserializer}, scene::{SceneInstance, Scene}, algorithms::RayMarcherBackend};
+use self::{background::Background, textures::{TextureData, textures_source}, primitives::{Primitive, PrimitiveIds, PrimitiveLibrary}, methods::{MethodDefinition, DataDeserializer, ImplicitParam}, scene::Scene, algorithms::{RayMarcherBackend, DebugView}, stats::{FrameStats, FrameSample, GpuTimer}};
 
 pub mod methods;
 pub mod scene;
 pub mod algorithms;
+pub mod primitives;
+pub mod cpu;
+pub mod background;
+pub mod camera;
+pub mod textures;
+pub mod bvh;
+pub mod scene_file;
+pub mod stats;
+pub mod math;
 
 pub const MAX_ROM_SIZE: usize = 3072;
+pub const DEFAULT_BOUNCE_DEPTH: u32 = 3;
+
+/// Interval helpers and the contract every bound method follows, included ahead of the registered methods.
+const BOUNDS_SOURCE: &str = include_str!("../../sdf/bounds.glsl");
+
+/// Distance to a surface at which the marcher counts it as hit.
+pub const HIT_DISTANCE: f32 = 0.01;
+/// Distance after which the marcher gives up on a ray.
+pub const MAX_DISTANCE: f32 = 1000.0;
+/// Instances past this many get no bound interval and are marched as if unbounded.
+pub const MAX_BOUNDED_INSTANCES: usize = 64;
+/// Direction scenes are lit from, unless the background has a sun.
+pub const DEFAULT_LIGHT_DIRECTION: [f32;3] = [0.3244,0.8111,-0.4867];
 
 pub struct Renderer<S: Scene, R: RayMarcherBackend, A: App<S, R>>{
-    registered_bounding_methods: Vec<(String,DataDeserializer)>,
-    registered_sdf_methods: Vec<(String,DataDeserializer)>,
-    registered_tex_methods: Vec<(String,DataDeserializer)>,
+    registered_bounding_methods: Vec<(String,DataDeserializer,Vec<ImplicitParam>)>,
+    registered_sdf_methods: Vec<(String,DataDeserializer,Vec<ImplicitParam>)>,
+    registered_tex_methods: Vec<(String,DataDeserializer,Vec<ImplicitParam>)>,
+    registered_textures: Vec<(String,TextureData)>,
     functionality: Vec<String>,
+    bounce_depth: u32,
+    background: Background,
+    fog_density: f32,
     timer: Instant,
-    old: f32,
-    frames: u32,
+    /// Frames drawn so far, numbers the frame stats independent of their window and wraps into the `frame_index` uniform
+    frames: u64,
+    /// Start of the previous draw
+    last_draw: Option<Instant>,
+    stats: FrameStats,
+    gpu_timer: Option<GpuTimer>,
+    /// Whether the last serialized scene had animation records
+    scene_animated: bool,
     scene: S,
     backend: R,
     app: MaybeUninit<A>
 }
 
 impl<S: Scene, R: RayMarcherBackend, A: App<S, R>> Renderer<S, R, A> {
-    pub fn new(ctx: &mut Context,scene: S, mut app: A) -> Self
+    pub fn new(ctx: &mut Context,scene: S, app: A) -> Self
+    {
+        let backend = R::new(ctx);
+        Self::with_backend(ctx, backend, scene, app)
+    }
+
+    /// Like `new`, for backends that were created with settings of their own.
+    pub fn with_backend(ctx: &mut Context, backend: R, scene: S, mut app: A) -> Self
     {
         let mut x = Self{
             functionality: Vec::new(),
+            bounce_depth: DEFAULT_BOUNCE_DEPTH,
+            background: Background::default(),
+            fog_density: 0.0,
             registered_bounding_methods: Vec::new(),
             registered_sdf_methods: Vec::new(),
             registered_tex_methods: Vec::new(),
+            registered_textures: Vec::new(),
             timer: Instant::now(),
             frames: 0,
-            old: 0.0,
+            last_draw: None,
+            stats: FrameStats::default(),
+            gpu_timer: GpuTimer::new(),
+            scene_animated: false,
             scene,
-            backend: R::new(ctx),
+            backend,
             app: MaybeUninit::uninit()
         };
         app.init(&mut x);
         x.app = MaybeUninit::new(app);
         let  fragment = x.get_scene_shader();
-        println!("{}",fragment);
+        let mut images = x.background.create_images(ctx);
+        images.extend(x.registered_textures.iter().map(|(name,data)| (name.clone(), data.create(ctx))));
+        x.backend.set_scene_images(images);
         x.backend.recreate_scene_shader(ctx, fragment);
         x
     }
@@ -60,20 +108,98 @@ impl<S: Scene, R: RayMarcherBackend, A: App<S, R>> Renderer<S, R, A> {
 
 
     pub fn register_bound_method(&mut self, method_name: String, deserializer: DataDeserializer) -> u32{
-        self.registered_bounding_methods.push((method_name,deserializer));
+        self.register_bound_method_with_implicit(method_name, deserializer, &[])
+    }
+
+    /// Same as `register_bound_method`, the method gets the implicit parameters after the ray, in the given order.
+    pub fn register_bound_method_with_implicit(&mut self, method_name: String, deserializer: DataDeserializer, implicit: &[ImplicitParam]) -> u32{
+        self.registered_bounding_methods.push((method_name,deserializer,implicit.to_vec()));
         self.registered_bounding_methods.len() as u32
     }
 
     pub fn register_sdf_method(&mut self, method_name: String, deserializer: DataDeserializer) -> u32{
-        self.registered_sdf_methods.push((method_name,deserializer));
+        self.register_sdf_method_with_implicit(method_name, deserializer, &[])
+    }
+
+    /// Same as `register_sdf_method`, the method gets the implicit parameters after the position, in the given order.
+    pub fn register_sdf_method_with_implicit(&mut self, method_name: String, deserializer: DataDeserializer, implicit: &[ImplicitParam]) -> u32{
+        self.registered_sdf_methods.push((method_name,deserializer,implicit.to_vec()));
         self.registered_sdf_methods.len() as u32
     }
 
+    /// Registers a tex method, it is called as `name(position, entries...)` and returns either a `Material` or a `vec4` color.
+    /// Colors are turned into a rough dielectric material, with the alpha channel used as opacity.
     pub fn register_tex_method(&mut self, method_name: String, deserializer: DataDeserializer) -> u32{
-        self.registered_tex_methods.push((method_name,deserializer));
+        self.register_tex_method_with_implicit(method_name, deserializer, &[])
+    }
+
+    /// Same as `register_tex_method`, the method is called as `name(position, implicit..., entries...)`.
+    pub fn register_tex_method_with_implicit(&mut self, method_name: String, deserializer: DataDeserializer, implicit: &[ImplicitParam]) -> u32{
+        self.registered_tex_methods.push((method_name,deserializer,implicit.to_vec()));
         self.registered_tex_methods.len() as u32
     }
 
+    /// Registers a texture as `uniform sampler2D name`, returning its slot for `sample_texture(slot, uv)`.
+    /// Slots can be stored in the rom and read with `DataEntry::texture_slot`.
+    pub fn register_texture(&mut self, name: String, data: TextureData) -> u32{
+        self.registered_textures.push((name,data));
+        self.registered_textures.len() as u32 - 1
+    }
+
+    pub fn scene_mut(&mut self) -> &mut S{
+        &mut self.scene
+    }
+
+    pub fn frame_stats(&self) -> &FrameStats{
+        &self.stats
+    }
+
+    /// For changing the window or exporting frames to CSV, usually from `App::init`.
+    pub fn frame_stats_mut(&mut self) -> &mut FrameStats{
+        &mut self.stats
+    }
+
+    /// Adds the sdf, bound and color methods of every built-in `Primitive`, returning the ids they got.
+    pub fn register_primitive_library(&mut self) -> PrimitiveLibrary{
+        let ids = Primitive::ALL.iter().map(|primitive|{
+            self.add_methods(MethodDefinition::Script(primitive.source().to_string()));
+            let ids = PrimitiveIds{
+                bound: self.register_bound_method(format!("bound_{}", primitive.name()), primitive.deserializer()),
+                sdf: self.register_sdf_method(format!("sdf_{}", primitive.name()), primitive.deserializer()),
+                tex: self.register_tex_method(format!("color_{}", primitive.name()), primitive.tex_deserializer()),
+            };
+            (*primitive, ids)
+        }).collect();
+        PrimitiveLibrary{
+            ids
+        }
+    }
+
+    /// Sets how many secondary rays a reflective or transparent hit may spawn.
+    /// Only takes effect when the scene shader is generated, so it should be called from `App::init`.
+    pub fn set_bounce_depth(&mut self, depth: u32){
+        self.bounce_depth = depth;
+    }
+
+    /// Sets what rays that hit nothing see, should be called from `App::init`.
+    pub fn set_background(&mut self, background: Background){
+        self.background = background;
+    }
+
+    /// Blends surfaces toward the background by `1 - exp(-density * distance)`, 0 disables fog.
+    /// Should be called from `App::init`.
+    pub fn set_fog_density(&mut self, density: f32){
+        self.fog_density = density;
+    }
+
+    /// Whether any registered method reads the time or frame index, and so changes without the rom changing.
+    fn has_animated_methods(&self) -> bool{
+        self.registered_bounding_methods.iter()
+            .chain(self.registered_sdf_methods.iter())
+            .chain(self.registered_tex_methods.iter())
+            .any(|(_,_,implicit)| !implicit.is_empty())
+    }
+
     fn get_scene_shader(&self ) -> String{
 
         format!("#version 330
@@ -82,98 +208,810 @@ impl<S: Scene, R: RayMarcherBackend, A: App<S, R>> Renderer<S, R, A> {
         
         out vec4 f_color;
         
+        uniform float fov_y;
         uniform float elapsed_time;
+        uniform int frame_index;
+        uniform int debug_view;
+        uniform int march_mode;
+        // Radius of the prepass cones per unit of distance along their ray
+        uniform float cone_radius;
 
         uniform vec3 position;
         uniform vec4 rotation;
+        uniform vec2 jitter;
 
         uniform int scene_rom[3072];
+
+        // Safe distance to start the primary ray of every prepass tile at, read with MARCH_SEEDED
+        uniform sampler2D start_distance_tex;
+
+        struct Material{{
+            vec3 albedo;
+            float roughness;
+            float metallic;
+            vec3 emission;
+            float opacity;
+            float ior;
+        }};
+
+        Material material_diffuse(in vec3 albedo){{
+            return Material(albedo, 1.0, 0.0, vec3(0.0), 1.0, 1.5);
+        }}
+
+        // Tex methods may return either a Material or a plain color
+        Material as_material(in Material material){{
+            return material;
+        }}
+
+        Material as_material(in vec4 color){{
+            Material material = material_diffuse(color.rgb);
+            material.opacity = color.a;
+            return material;
+        }}
+
+        const float HIT_DISTANCE = {hit_distance:?};
+        const float MAX_DISTANCE = {max_distance:?};
+
+        {bounds}
+
+        {textures}
         
         //method definitions
         {0}
-        
+
+        {background}
+
         struct HitInfo{{
             float dist;
             int id;
         }};
+
+        const int OP_GROUP_BEGIN = {op_group_begin};
+        const int OP_GROUP_END = {op_group_end};
+        const int OP_TRANSFORM = {op_transform};
+        const int OP_DOMAIN = {op_domain};
+        const int OP_ANIMATE = {op_animate};
+        const int OP_BVH_NODE = {op_bvh_node};
+
+        const int DOMAIN_REPEAT = {domain_repeat};
+        const int DOMAIN_REPEAT_LIMITED = {domain_repeat_limited};
+        const int DOMAIN_MIRROR = {domain_mirror};
+        const int DOMAIN_TWIST = {domain_twist};
+        const int DOMAIN_BEND = {domain_bend};
+        const int MAX_GROUP_DEPTH = {max_group_depth};
+
+        const int ANIMATION_OSCILLATE = {animation_oscillate};
+        const int ANIMATION_SPIN = {animation_spin};
+        const int ANIMATION_KEYFRAMES = {animation_keyframes};
+        const int MAX_KEYFRAMES = {max_keyframes};
+
+        const int CSG_UNION = {csg_union};
+        const int CSG_SUBTRACT = {csg_subtract};
+        const int CSG_INTERSECT = {csg_intersect};
+        const int CSG_SMOOTH_UNION = {csg_smooth_union};
+        const int CSG_SMOOTH_SUBTRACT = {csg_smooth_subtract};
+        const int CSG_SMOOTH_INTERSECT = {csg_smooth_intersect};
+
+        // Combines the next member b into the accumulated group a, subtraction keeps the material of a
+        HitInfo csg(in HitInfo a, in HitInfo b, int op, float k){{
+            switch (op){{
+                case CSG_SUBTRACT: return -b.dist > a.dist ? HitInfo(-b.dist, a.id) : a;
+                case CSG_INTERSECT: return b.dist > a.dist ? b : a;
+                case CSG_SMOOTH_UNION: {{
+                    float h = clamp(0.5 + 0.5 * (b.dist - a.dist) / k, 0.0, 1.0);
+                    return HitInfo(mix(b.dist, a.dist, h) - k * h * (1.0 - h), h > 0.5 ? a.id : b.id);
+                }}
+                case CSG_SMOOTH_SUBTRACT: {{
+                    float h = clamp(0.5 - 0.5 * (a.dist + b.dist) / k, 0.0, 1.0);
+                    return HitInfo(mix(a.dist, -b.dist, h) + k * h * (1.0 - h), a.id);
+                }}
+                case CSG_SMOOTH_INTERSECT: {{
+                    float h = clamp(0.5 - 0.5 * (b.dist - a.dist) / k, 0.0, 1.0);
+                    return HitInfo(mix(b.dist, a.dist, h) + k * h * (1.0 - h), h > 0.5 ? a.id : b.id);
+                }}
+                default: return b.dist < a.dist ? b : a;
+            }}
+        }}
+        
+        vec3 quat_rotate(in vec4 q, in vec3 v){{
+            return v + 2.0 * cross(q.xyz, cross(q.xyz, v) + q.w * v);
+        }}
+
+        vec3 read_vec3(int pnt){{
+            return vec3(
+                intBitsToFloat(scene_rom[pnt]),
+                intBitsToFloat(scene_rom[pnt+1]),
+                intBitsToFloat(scene_rom[pnt+2]));
+        }}
+
+        vec4 read_vec4(int pnt){{
+            return vec4(read_vec3(pnt), intBitsToFloat(scene_rom[pnt+3]));
+        }}
+
+        // Motion of the animation record at pnt at the current time, as a translation and a unit quaternion
+        void animate(int kind, int pnt, out vec3 translation, out vec4 rotation){{
+            translation = vec3(0.0);
+            rotation = vec4(0.0,0.0,0.0,1.0);
+            switch (kind){{
+                case ANIMATION_OSCILLATE: {{
+                    float frequency = intBitsToFloat(scene_rom[pnt+3]);
+                    float phase = intBitsToFloat(scene_rom[pnt+4]);
+                    translation = read_vec3(pnt) * sin(6.2831853 * frequency * elapsed_time + phase);
+                }} break;
+                case ANIMATION_SPIN: {{
+                    float angle = intBitsToFloat(scene_rom[pnt+3]) * elapsed_time;
+                    rotation = vec4(read_vec3(pnt) * sin(angle * 0.5), cos(angle * 0.5));
+                }} break;
+                case ANIMATION_KEYFRAMES: {{
+                    int count = scene_rom[pnt];
+                    int keys = pnt + 2;
+                    float first = intBitsToFloat(scene_rom[keys]);
+                    float last = intBitsToFloat(scene_rom[keys + (count - 1) * 4]);
+                    float t = elapsed_time;
+                    if (scene_rom[pnt+1] != 0 && last > first){{
+                        t = first + mod(t - first, last - first);
+                    }}
+
+                    translation = read_vec3(keys+1);
+                    for (int i = 1; i < MAX_KEYFRAMES && i < count; i++){{
+                        int key = keys + i * 4;
+                        float key_time = intBitsToFloat(scene_rom[key]);
+                        if (t < key_time){{
+                            float previous_time = intBitsToFloat(scene_rom[key-4]);
+                            float f = clamp((t - previous_time) / max(key_time - previous_time, 0.0001), 0.0, 1.0);
+                            translation = mix(read_vec3(key-3), read_vec3(key+1), f);
+                            break;
+                        }}
+                        translation = read_vec3(key+1);
+                    }}
+                }} break;
+            }}
+        }}
+
+        // Remaps position into the domain of the modifier at pnt,
+        // returns the factor distances have to be scaled by to stay conservative
+        float apply_domain(int kind, int pnt, inout vec3 position){{
+            switch (kind){{
+                case DOMAIN_REPEAT: {{
+                    vec3 period = read_vec3(pnt);
+                    vec3 repeated = position - period * round(position / max(period, vec3(0.0001)));
+                    position = mix(position, repeated, step(vec3(0.0001), period));
+                    return 1.0;
+                }}
+                case DOMAIN_REPEAT_LIMITED: {{
+                    vec3 period = read_vec3(pnt);
+                    vec3 limit = read_vec3(pnt+3);
+                    vec3 repeated = position - period * clamp(round(position / max(period, vec3(0.0001))), -limit, limit);
+                    position = mix(position, repeated, step(vec3(0.0001), period));
+                    return 1.0;
+                }}
+                case DOMAIN_MIRROR: {{
+                    position = mix(position, abs(position), read_vec3(pnt));
+                    return 1.0;
+                }}
+                case DOMAIN_TWIST: {{
+                    float k = intBitsToFloat(scene_rom[pnt]);
+                    float c = cos(k * position.y);
+                    float s = sin(k * position.y);
+                    position = vec3(mat2(c,-s,s,c) * position.xz, position.y).xzy;
+                    return 1.0 / sqrt(1.0 + k * k * dot(position.xz, position.xz));
+                }}
+                case DOMAIN_BEND: {{
+                    float k = intBitsToFloat(scene_rom[pnt]);
+                    float c = cos(k * position.x);
+                    float s = sin(k * position.x);
+                    position = vec3(mat2(c,-s,s,c) * position.xy, position.z);
+                    return 1.0 / sqrt(1.0 + k * k * dot(position.xy, position.xy));
+                }}
+                default: return 1.0;
+            }}
+        }}
+
+        // Index just past the data of the record at pnt, whose length is stored at pnt+1
+        int skip_record(int pnt){{
+            return pnt + 2 + scene_rom[pnt+1];
+        }}
+
+        // Reads the bvh node record at pnt and moves pnt past it
+        void read_bvh_node(inout int pnt, out int skip, out int count, out vec3 box_min, out vec3 box_max){{
+            skip = scene_rom[pnt];
+            count = scene_rom[pnt+1];
+            box_min = read_vec3(pnt+2);
+            box_max = read_vec3(pnt+5);
+            pnt += 8;
+        }}
+
+        // Space a transform or animation record moves its child into, animations are rigid and keep a scale of 1
+        struct LocalSpace{{
+            vec3 translation;
+            vec4 inverse_rotation;
+            vec3 scale;
+        }};
+
+        // Reads the transform or animation record at pnt and moves pnt past it
+        LocalSpace read_space(int op, inout int pnt){{
+            LocalSpace space;
+            if (op == OP_TRANSFORM){{
+                space = LocalSpace(read_vec3(pnt), read_vec4(pnt+3) * vec4(-1.0,-1.0,-1.0,1.0), read_vec3(pnt+7));
+                pnt += 10;
+            }}
+            else{{
+                vec3 translation;
+                vec4 rotation;
+                animate(scene_rom[pnt], pnt+2, translation, rotation);
+                space = LocalSpace(translation, rotation * vec4(-1.0,-1.0,-1.0,1.0), vec3(1.0));
+                pnt = skip_record(pnt);
+            }}
+            return space;
+        }}
+
+        vec3 space_point(in LocalSpace space, in vec3 point){{
+            return quat_rotate(space.inverse_rotation, point - space.translation) / space.scale;
+        }}
+
+        // Not normalized, its length tells how much the space stretches the direction
+        vec3 space_direction(in LocalSpace space, in vec3 direction){{
+            return quat_rotate(space.inverse_rotation, direction) / space.scale;
+        }}
+
+        // Factor distances measured in the space are scaled by to stay conservative
+        float space_distance_scale(in LocalSpace space){{
+            return min(space.scale.x, min(space.scale.y, space.scale.z));
+        }}
+
+        const int MAX_BOUNDED_INSTANCES = {max_bounded_instances};
+
+        // World space [tmin, tmax] of every instance along the ray being marched, filled in by prepare_bounds.
+        // Instances past the end of the array are treated as unbounded.
+        vec2 instance_intervals[MAX_BOUNDED_INSTANCES];
+
+        // Bound methods called by the last prepare_bounds, and how many of them culled the ray
+        int bound_checks = 0;
+        int bound_misses = 0;
+
+        // Evaluates every bound once for the ray, returns the distance at which the nearest instance can be entered
+        float prepare_bounds(in vec3 world_origin, in vec3 world_ray){{
+            int pnt = 0;
+            int depth = 0;
+            int instance = 0;
+            float nearest = BOUND_FAR;
+            bound_checks = 0;
+            bound_misses = 0;
+
+            vec3 group_origin[MAX_GROUP_DEPTH];
+            vec3 group_ray[MAX_GROUP_DEPTH];
+            float group_ray_scale[MAX_GROUP_DEPTH];
+            bool group_bounded[MAX_GROUP_DEPTH];
+            bool group_blended[MAX_GROUP_DEPTH];
+            group_origin[0] = world_origin;
+            group_ray[0] = world_ray;
+            group_ray_scale[0] = 1.0;
+            group_bounded[0] = true;
+            group_blended[0] = false;
+
+            vec3 origin = world_origin;
+            vec3 ray = world_ray;
+            // Length of the local ray per unit of the world ray, converts local intervals back to world distances
+            float ray_scale = 1.0;
+            // Domain modifiers bend space, so the ray no longer says anything about which members can be hit
+            bool bounded = true;
+            // Smooth groups blend outside the bounds of their members, so those can't move the start of the march
+            bool blended = false;
+
+            while(true){{
+                int bound_type = scene_rom[pnt];
+                pnt += 1;
+
+                // Bvh nodes only appear in the top level union, where the ray is still in world space
+                if (bound_type == OP_BVH_NODE){{
+                    int skip, count;
+                    vec3 box_min, box_max;
+                    read_bvh_node(pnt, skip, count, box_min, box_max);
+
+                    vec2 interval = bound_ray_box(origin, ray, (box_min + box_max) * 0.5, (box_max - box_min) * 0.5);
+                    if (interval.x > interval.y){{
+                        for (int i = instance; i < min(instance + count, MAX_BOUNDED_INSTANCES); i++){{
+                            instance_intervals[i] = BOUND_MISS;
+                        }}
+                        instance += count;
+                        pnt += skip;
+                    }}
+                    continue;
+                }}
+
+                if (bound_type == OP_TRANSFORM || bound_type == OP_ANIMATE){{
+                    LocalSpace space = read_space(bound_type, pnt);
+                    origin = space_point(space, origin);
+                    vec3 local_ray = space_direction(space, ray);
+                    ray_scale *= length(local_ray);
+                    ray = normalize(local_ray);
+                    continue;
+                }}
+
+                if (bound_type == OP_DOMAIN){{
+                    pnt = skip_record(pnt);
+                    bounded = false;
+                    continue;
+                }}
+
+                if (bound_type == OP_GROUP_BEGIN){{
+                    depth += 1;
+                    group_origin[depth] = origin;
+                    group_ray[depth] = ray;
+                    group_ray_scale[depth] = ray_scale;
+                    group_bounded[depth] = bounded;
+                    blended = blended || scene_rom[pnt] >= CSG_SMOOTH_UNION;
+                    group_blended[depth] = blended;
+                    pnt += 2;
+                    continue;
+                }}
+
+                if (bound_type != OP_GROUP_END){{
+                    vec2 interval = vec2(0.0, BOUND_FAR);
+                    switch(bound_type){{
+                        case 0: break;
+                        {1}
+                        default: break;
+                    }}
+
+                    int sdf_type = scene_rom[pnt];
+                    pnt += 1;
+                    if (sdf_type == 0){{
+                        break;
+                    }}
+                    switch(sdf_type){{
+                        {sdf_skip_cases}
+                        default: break;
+                    }}
+                    pnt += 1 + scene_rom[pnt];
+
+                    if (bound_type != 0){{
+                        bound_checks += 1;
+                        bound_misses += interval.x > interval.y ? 1 : 0;
+                    }}
+
+                    interval = bounded ? interval / ray_scale : vec2(0.0, BOUND_FAR);
+                    if (instance < MAX_BOUNDED_INSTANCES){{
+                        instance_intervals[instance] = interval;
+                    }}
+                    instance += 1;
+                    if (interval.x <= interval.y){{
+                        nearest = min(nearest, blended ? 0.0 : interval.x);
+                    }}
+                }}
+                else{{
+                    depth -= 1;
+                }}
+
+                origin = group_origin[depth];
+                ray = group_ray[depth];
+                ray_scale = group_ray_scale[depth];
+                bounded = group_bounded[depth];
+                blended = group_blended[depth];
+            }}
+
+            return instance > MAX_BOUNDED_INSTANCES ? 0.0 : nearest;
+        }}
         
-        HitInfo sdf_scene(in vec3 origin, in vec3 position, in vec3 ray){{
+        // Expects prepare_bounds to have been called with the same origin and ray
+        HitInfo sdf_scene(in vec3 world_origin, in vec3 world_position, in vec3 world_ray){{
             int pnt = 0;
-            HitInfo curHit = HitInfo(1001.0,0);
+            int instance = 0;
+            float t = dot(world_position - world_origin, world_ray);
+
+            // Group 0 is the implicit union of the whole scene
+            int depth = 0;
+            HitInfo group_hit[MAX_GROUP_DEPTH];
+            int group_op[MAX_GROUP_DEPTH];
+            float group_k[MAX_GROUP_DEPTH];
+            bool group_empty[MAX_GROUP_DEPTH];
+            group_hit[0] = HitInfo(MAX_DISTANCE + 1.0,0);
+            group_op[0] = CSG_UNION;
+            group_k[0] = 0.0;
+            group_empty[0] = true;
+
+            // Space every member of a group starts out in, transform records move the current space further
+            vec3 group_origin[MAX_GROUP_DEPTH];
+            vec3 group_position[MAX_GROUP_DEPTH];
+            vec3 group_ray[MAX_GROUP_DEPTH];
+            float group_scale[MAX_GROUP_DEPTH];
+            group_origin[0] = world_origin;
+            group_position[0] = world_position;
+            group_ray[0] = world_ray;
+            group_scale[0] = 1.0;
+
+            vec3 origin = world_origin;
+            vec3 position = world_position;
+            vec3 ray = world_ray;
+            float dist_scale = 1.0;
         
             bool running = true;
             while(running){{
                 int bound_type = scene_rom[pnt];
                 pnt += 1;
-                bool hitable = true;
-                switch(bound_type){{
-                    case 0: break;
-                    {1}
-                    default: break;
+
+                if (bound_type == OP_BVH_NODE){{
+                    int skip, count;
+                    vec3 box_min, box_max;
+                    read_bvh_node(pnt, skip, count, box_min, box_max);
+
+                    // Nothing in the subtree can come closer than its box. Inside a solid the union is negative,
+                    // boxes around the position still have to be entered since they can lower it further
+                    vec3 outside = max(box_min - position, position - box_max);
+                    if (!group_empty[0] && length(max(outside, 0.0)) > max(group_hit[0].dist, 0.0)){{
+                        instance += count;
+                        pnt += skip;
+                    }}
+                    continue;
                 }}
 
-                
-                int sdf_type = scene_rom[pnt];
-                pnt += 1;
-                switch (sdf_type){{
-                    case 0: {{
-                        running = false;
-                    }}break;
+                if (bound_type == OP_TRANSFORM || bound_type == OP_ANIMATE){{
+                    LocalSpace space = read_space(bound_type, pnt);
+                    origin = space_point(space, origin);
+                    position = space_point(space, position);
+                    ray = normalize(space_direction(space, ray));
+                    dist_scale *= space_distance_scale(space);
+                    continue;
+                }}
+
+                if (bound_type == OP_DOMAIN){{
+                    dist_scale *= apply_domain(scene_rom[pnt], pnt+2, position);
+                    pnt = skip_record(pnt);
+                    continue;
+                }}
+
+                if (bound_type == OP_GROUP_BEGIN){{
+                    depth += 1;
+                    group_hit[depth] = HitInfo(MAX_DISTANCE + 1.0,0);
+                    group_op[depth] = scene_rom[pnt];
+                    group_k[depth] = intBitsToFloat(scene_rom[pnt+1]);
+                    group_empty[depth] = true;
+                    group_origin[depth] = origin;
+                    group_position[depth] = position;
+                    group_ray[depth] = ray;
+                    group_scale[depth] = dist_scale;
+                    pnt += 2;
+                    continue;
+                }}
+
+                HitInfo hit = HitInfo(MAX_DISTANCE + 1.0,0);
+
+                if (bound_type == OP_GROUP_END){{
+                    hit = group_hit[depth];
+                    depth -= 1;
+                }}
+                else{{
+                    // Bounds were already evaluated by prepare_bounds
+                    switch(bound_type){{
+                        case 0: break;
+                        {bound_skip_cases}
+                        default: break;
+                    }}
+                    vec2 interval = instance < MAX_BOUNDED_INSTANCES ? instance_intervals[instance] : vec2(0.0, BOUND_FAR);
+                    instance += 1;
+
+                    // Outside of groups the scene is a plain union, so instances the ray has passed can be dropped
+                    // and the distance to the entry of instances ahead is a safe step
+                    bool passed = depth == 0 && t > interval.y;
+                    bool ahead = depth == 0 && t < interval.x && interval.x <= interval.y;
+                    bool hitable = interval.x <= interval.y && !passed && !ahead;
+
+                    int sdf_type = scene_rom[pnt];
+                    pnt += 1;
+                    switch (sdf_type){{
+                        case 0: {{
+                            running = false;
+                        }}break;
+
+                        {2}
+
+                        default: {{
+                            running = false;
+                        }}break;
+                    }}
+
+                    // Distances measured in scaled space are corrected by the smallest scale to stay conservative.
+                    // Rounding can leave a step that ends on a bound entry just short of it, the entry step never
+                    // counts as a hit since bounds keep a margin of twice the hit distance around the surface.
+                    hit.dist = ahead ? max(interval.x - t, HIT_DISTANCE) : hit.dist * dist_scale;
+                }}
 
-                    {2}
+                origin = group_origin[depth];
+                position = group_position[depth];
+                ray = group_ray[depth];
+                dist_scale = group_scale[depth];
 
-                    default: {{
-                        running = false;
-                    }}break;
+                if (running){{
+                    group_hit[depth] = group_empty[depth] ? hit : csg(group_hit[depth], hit, group_op[depth], group_k[depth]);
+                    group_empty[depth] = false;
                 }}
-                
             }}
         
-            return curHit;
+            return group_hit[0];
         }}
         
-        vec4 color(int pnt, in vec3 position){{
+        Material material(int pnt, in vec3 position){{
             int tex_type = scene_rom[pnt];
             pnt += 1;
             switch (tex_type){{
-                case 0: return vec4(1.0,0.0,1.0,1.0);
+                case 0: return material_diffuse(vec3(1.0,0.0,1.0));
                 {3}
-                default: return vec4(1.0,0.0,1.0,1.0);
+                default: return material_diffuse(vec3(1.0,0.0,1.0));
             }}
         }}
-        
-        void main(){{
-        
-            vec3 ray = normalize(vec3(f_pos,2.0));
-            
-            vec3 hit_position = position;
-            HitInfo cur = HitInfo(0.0,0);
-            float traveled = 0.0;
-            float u = 255.0;
+
+        const int MAX_BOUNCES = {4};
+        const float FOCAL_LENGTH = {focal_length:?};
+        const float NEAR_PLANE = {near_plane:?};
+        const float FAR_PLANE = {far_plane:?};
+
+        // Depth buffer value of a hit under camera::projection, so rasterized meshes can be tested against the scene
+        float hit_depth(in vec3 hit_position){{
+            float z = max(hit_position.z - position.z, NEAR_PLANE);
+            float ndc = (FAR_PLANE + NEAR_PLANE) / (FAR_PLANE - NEAR_PLANE) - 2.0 * FAR_PLANE * NEAR_PLANE / ((FAR_PLANE - NEAR_PLANE) * z);
+            return ndc * 0.5 + 0.5;
+        }}
+
+        const vec3 LIGHT_DIRECTION = vec3({light_direction});
+        const float FOG_DENSITY = {fog_density:?};
+        const float AMBIENT = 0.2;
+
+        struct MarchInfo{{
+            HitInfo hit;
+            vec3 position;
+            int steps;
+        }};
+
+        const int MARCH_FULL = 0;
+        const int MARCH_CONE = 1;
+        const int MARCH_SEEDED = 2;
+
+        // side is 1.0 when marching through empty space and -1.0 when marching inside a solid,
+        // nothing is hit before start
+        MarchInfo march(in vec3 origin, in vec3 ray, float side, float start){{
+            float traveled = max(prepare_bounds(origin, ray), start);
+            vec3 hit_position = origin + ray * traveled;
+            HitInfo cur = HitInfo(MAX_DISTANCE + 1.0,0);
+            if (traveled > MAX_DISTANCE){{
+                return MarchInfo(cur, hit_position, 0);
+            }}
+            int steps = 255;
             for (int i = 0; i < 256; i++){{
-                cur = sdf_scene(position, hit_position, ray);
+                cur = sdf_scene(origin, hit_position, ray);
+                cur.dist *= side;
                 traveled += cur.dist;
-                hit_position = position + ray * traveled;
-                if (cur.dist < 0.01 || traveled > 1000.0){{
-                    u = float(i);
+                hit_position = origin + ray * traveled;
+                if (cur.dist < HIT_DISTANCE || traveled > MAX_DISTANCE){{
+                    steps = i;
                     break;
                 }}
             }}
-        
-            if (cur.dist < 0.01){{
-                f_color = color(cur.id,hit_position);
-        
+            return MarchInfo(cur, hit_position, steps);
+        }}
+
+        // Distance every ray in the cone around ray can travel without touching a surface.
+        // Bounds only hold for a single ray, so every instance is marched.
+        float cone_march(in vec3 origin, in vec3 ray){{
+            for (int i = 0; i < MAX_BOUNDED_INSTANCES; i++){{
+                instance_intervals[i] = vec2(0.0, BOUND_FAR);
             }}
-            else{{
-                u /= 64;
-                f_color = vec4(u,u,u,1.0);
+            float traveled = 0.0;
+            for (int i = 0; i < 256; i++){{
+                float dist = sdf_scene(origin, origin + ray * traveled, ray).dist;
+                // Rays of the cone stay within the distance of the surface up to here
+                float next = (traveled + dist) / (1.0 + cone_radius);
+                bool done = next - traveled < HIT_DISTANCE;
+                traveled = max(traveled, min(next, MAX_DISTANCE));
+                if (done || traveled >= MAX_DISTANCE){{
+                    break;
+                }}
             }}
+            return traveled;
+        }}
+
+        vec3 normal(in vec3 origin, in vec3 position, in vec3 ray){{
+            const vec2 k = vec2(1.0,-1.0);
+            const float h = 0.001;
+            return normalize(
+                k.xyy * sdf_scene(origin, position + k.xyy * h, ray).dist +
+                k.yyx * sdf_scene(origin, position + k.yyx * h, ray).dist +
+                k.yxy * sdf_scene(origin, position + k.yxy * h, ray).dist +
+                k.xxx * sdf_scene(origin, position + k.xxx * h, ray).dist);
+        }}
+
+        // Direct lighting of a surface, reflections and refractions are traced in main
+        vec3 shade(in Material material, in vec3 n, in vec3 ray){{
+            float diffuse = max(dot(n, LIGHT_DIRECTION), 0.0);
+            vec3 half_vector = normalize(LIGHT_DIRECTION - ray);
+            float shininess = mix(256.0, 4.0, material.roughness);
+            float specular = pow(max(dot(n, half_vector), 0.0), shininess) * (1.0 - material.roughness);
+            vec3 specular_color = mix(vec3(1.0), material.albedo, material.metallic);
+
+            return material.albedo * (1.0 - material.metallic) * (AMBIENT + (1.0 - AMBIENT) * diffuse)
+                + specular_color * specular;
+        }}
+
+        const int DEBUG_STEPS = {debug_steps};
+        const int DEBUG_DEPTH = {debug_depth};
+        const int DEBUG_NORMALS = {debug_normals};
+        const int DEBUG_INSTANCE_ID = {debug_instance_id};
+        const int DEBUG_BOUND_MASK = {debug_bound_mask};
+        const int DEBUG_SDF_VALUE = {debug_sdf_value};
+
+        // Blue at 0, green at 0.5 and red at 1
+        vec3 heat(float t){{
+            t = clamp(t, 0.0, 1.0);
+            return mix(mix(vec3(0.0,0.0,1.0), vec3(0.0,1.0,0.0), clamp(t * 2.0, 0.0, 1.0)), vec3(1.0,0.0,0.0), clamp(t * 2.0 - 1.0, 0.0, 1.0));
+        }}
+
+        // Color of the primary ray under the selected debug view, expects cur to be the result of its march
+        vec3 debug_color(in MarchInfo cur, in vec3 origin, in vec3 ray){{
+            bool hit = cur.hit.dist < HIT_DISTANCE;
+            switch (debug_view){{
+                case DEBUG_STEPS: return heat(float(cur.steps) / 255.0);
+                case DEBUG_DEPTH: return hit ? vec3(1.0 - log(1.0 + distance(origin, cur.position)) / log(1.0 + MAX_DISTANCE)) : vec3(0.0);
+                case DEBUG_NORMALS: return hit ? normal(origin, cur.position, ray) * 0.5 + 0.5 : vec3(0.0);
+                case DEBUG_INSTANCE_ID: {{
+                    if (!hit) return vec3(0.0);
+                    // Golden ratio steps spread neighbouring rom offsets over the hue circle
+                    float hue = fract(float(cur.hit.id) * 0.618034);
+                    return clamp(abs(mod(hue * 6.0 + vec3(0.0,4.0,2.0), 6.0) - 3.0) - 1.0, 0.0, 1.0);
+                }}
+                case DEBUG_BOUND_MASK: return bound_checks == 0 ? vec3(0.0) : heat(float(bound_misses) / float(bound_checks));
+                case DEBUG_SDF_VALUE: {{
+                    float d = cur.hit.dist;
+                    if (d < 0.0) return vec3(0.0, 0.0, clamp(-d / HIT_DISTANCE, 0.25, 1.0));
+                    if (d < HIT_DISTANCE) return vec3(0.0, 0.25 + 0.75 * d / HIT_DISTANCE, 0.0);
+                    return mix(vec3(0.0,1.0,0.0), vec3(1.0,0.0,0.0), clamp(log(d / HIT_DISTANCE) / log(MAX_DISTANCE / HIT_DISTANCE), 0.0, 1.0));
+                }}
+                default: return vec3(1.0,0.0,1.0);
+            }}
+        }}
+
+        void main(){{
+
+            vec3 ray = normalize(vec3(f_pos + jitter,FOCAL_LENGTH));
+            vec3 origin = position;
+            float side = 1.0;
+
+            if (march_mode == MARCH_CONE){{
+                // Kept a little short, the prepass target only stores half floats
+                f_color = vec4(max(cone_march(origin, ray) * 0.998 - 2.0 * HIT_DISTANCE, 0.0));
+                gl_FragDepth = 1.0;
+                return;
+            }}
+            float start = march_mode == MARCH_SEEDED ? texture(start_distance_tex, vec2(f_pos.x + jitter.x, (f_pos.y + jitter.y) / fov_y) * 0.5 + 0.5).r : 0.0;
+
+            vec3 throughput = vec3(1.0);
+            vec3 result = vec3(0.0);
+            float depth = 1.0;
+
+            for (int bounce = 0; bounce <= MAX_BOUNCES; bounce++){{
+                MarchInfo cur = march(origin, ray, side, bounce == 0 ? start : 0.0);
+
+                if (debug_view != 0){{
+                    f_color = vec4(debug_color(cur, origin, ray), 1.0);
+                    gl_FragDepth = cur.hit.dist < HIT_DISTANCE ? hit_depth(cur.position) : 1.0;
+                    return;
+                }}
+
+                if (cur.hit.dist >= HIT_DISTANCE){{
+                    result += throughput * background(ray);
+                    break;
+                }}
+
+                // Fog only fills the space between solids
+                if (side > 0.0){{
+                    float fog = 1.0 - exp(-FOG_DENSITY * distance(origin, cur.position));
+                    result += throughput * fog * background(ray);
+                    throughput *= 1.0 - fog;
+                }}
+
+                if (bounce == 0){{
+                    depth = hit_depth(cur.position);
+                }}
+
+                // Facing the incoming ray, so it points inwards while we are inside a solid
+                vec3 n = normal(origin, cur.position, ray) * side;
+                hit_normal = n * side;
+
+                Material mat = material(cur.hit.id, cur.position);
+
+                vec3 base = shade(mat, n, ray);
+                result += throughput * mat.emission;
+
+                float f0 = (1.0 - mat.ior) / (1.0 + mat.ior);
+                f0 = mix(f0 * f0, 1.0, mat.metallic);
+                float fresnel = f0 + (1.0 - f0) * pow(1.0 - clamp(dot(-ray, n), 0.0, 1.0), 5.0);
+
+                float kr = fresnel * (1.0 - mat.roughness);
+                float kt = 0.0;
+                vec3 refracted = vec3(0.0);
+                if (mat.opacity < 1.0){{
+                    float eta = side > 0.0 ? 1.0 / mat.ior : mat.ior;
+                    refracted = refract(ray, n, eta);
+                    if (dot(refracted, refracted) == 0.0){{
+                        // Total internal reflection
+                        kr = 1.0;
+                    }}
+                    else{{
+                        kr = max(kr, fresnel);
+                        kt = (1.0 - mat.opacity) * (1.0 - kr);
+                    }}
+                }}
+                float kd = max(1.0 - kr - kt, 0.0);
+
+                if (bounce == MAX_BOUNCES || (kr <= 0.0 && kt <= 0.0)){{
+                    result += throughput * base;
+                    break;
+                }}
+
+                // Only the dominant secondary ray is followed, the other one is shaded with the surface color
+                if (kt > kr){{
+                    result += throughput * (kd + kr) * base;
+                    throughput *= kt * mat.albedo;
+                    origin = cur.position - n * HIT_DISTANCE * 3.0;
+                    ray = refracted;
+                    side = -side;
+                }}
+                else{{
+                    result += throughput * (kd + kt) * base;
+                    throughput *= kr * mix(vec3(1.0), mat.albedo, mat.metallic);
+                    origin = cur.position + n * HIT_DISTANCE * 3.0;
+                    ray = reflect(ray, n);
+                }}
+            }}
+
+            f_color = vec4(result,1.0);
+            gl_FragDepth = depth;
         }}
         ",
         self.functionality.join("\n"),
-        self.registered_bounding_methods.iter().enumerate().map(|(id,(name,deserializer))| deserializer.create_bounding_case(id as u32 + 1, name)).collect::<Vec<String>>().join("\n"),
-        self.registered_sdf_methods.iter().enumerate().map(|(id,(name,deserializer))| deserializer.create_sdf_case(id as u32 + 1, name)).collect::<Vec<String>>().join("\n"),
-        self.registered_tex_methods.iter().enumerate().map(|(id,(name,deserializer))| deserializer.create_tex_case(id as u32 + 1, name)).collect::<Vec<String>>().join("\n")
+        self.registered_bounding_methods.iter().enumerate().map(|(id,(name,deserializer,implicit))| deserializer.create_bounding_case_with_implicit(id as u32 + 1, name, implicit)).collect::<Vec<String>>().join("\n"),
+        self.registered_sdf_methods.iter().enumerate().map(|(id,(name,deserializer,implicit))| deserializer.create_sdf_case_with_implicit(id as u32 + 1, name, implicit)).collect::<Vec<String>>().join("\n"),
+        self.registered_tex_methods.iter().enumerate().map(|(id,(name,deserializer,implicit))| deserializer.create_tex_case_with_implicit(id as u32 + 1, name, implicit)).collect::<Vec<String>>().join("\n"),
+        self.bounce_depth,
+        background = self.background.source(),
+        textures = textures_source(&self.registered_textures.iter().map(|(name,_)| name.as_str()).collect::<Vec<_>>()),
+        light_direction = self.background.light_direction().unwrap_or(DEFAULT_LIGHT_DIRECTION).map(|x| format!("{:?}", x)).join(","),
+        fog_density = self.fog_density,
+        focal_length = camera::FOCAL_LENGTH,
+        near_plane = camera::NEAR_PLANE,
+        far_plane = camera::FAR_PLANE,
+        bound_skip_cases = self.registered_bounding_methods.iter().enumerate().map(|(id,(_,deserializer,_))| deserializer.create_skip_case(id as u32 + 1)).collect::<Vec<String>>().join("\n"),
+        sdf_skip_cases = self.registered_sdf_methods.iter().enumerate().map(|(id,(_,deserializer,_))| deserializer.create_skip_case(id as u32 + 1)).collect::<Vec<String>>().join("\n"),
+        max_bounded_instances = MAX_BOUNDED_INSTANCES,
+        hit_distance = HIT_DISTANCE,
+        max_distance = MAX_DISTANCE,
+        bounds = BOUNDS_SOURCE,
+        op_group_begin = OP_GROUP_BEGIN,
+        op_group_end = OP_GROUP_END,
+        op_transform = OP_TRANSFORM,
+        op_domain = OP_DOMAIN,
+        op_animate = OP_ANIMATE,
+        op_bvh_node = OP_BVH_NODE,
+        animation_oscillate = Animation::Oscillate{ amplitude: [0.0;3], frequency: 0.0, phase: 0.0 }.code(),
+        animation_spin = Animation::Spin{ axis: [0.0;3], speed: 0.0 }.code(),
+        animation_keyframes = Animation::Keyframes{ keys: Vec::new(), looping: false }.code(),
+        max_keyframes = MAX_KEYFRAMES,
+        debug_steps = DebugView::Steps.code(),
+        debug_depth = DebugView::Depth.code(),
+        debug_normals = DebugView::Normals.code(),
+        debug_instance_id = DebugView::InstanceId.code(),
+        debug_bound_mask = DebugView::BoundMask.code(),
+        debug_sdf_value = DebugView::SdfValue.code(),
+        domain_repeat = DomainModifier::Repeat{ period: [0.0;3] }.code(),
+        domain_repeat_limited = DomainModifier::RepeatLimited{ period: [0.0;3], limit: [0.0;3] }.code(),
+        domain_mirror = DomainModifier::Mirror{ axes: [false;3] }.code(),
+        domain_twist = DomainModifier::Twist{ strength: 0.0 }.code(),
+        domain_bend = DomainModifier::Bend{ strength: 0.0 }.code(),
+        max_group_depth = MAX_GROUP_DEPTH,
+        csg_union = CsgOp::Union.code(),
+        csg_subtract = CsgOp::Subtract.code(),
+        csg_intersect = CsgOp::Intersect.code(),
+        csg_smooth_union = CsgOp::SmoothUnion(0.0).code(),
+        csg_smooth_subtract = CsgOp::SmoothSubtract(0.0).code(),
+        csg_smooth_intersect = CsgOp::SmoothIntersect(0.0).code(),
         )
     }
 
@@ -190,21 +1028,62 @@ impl<T: Scene, R: RayMarcherBackend, A: App<T, R>> EventHandler for Renderer<T,
     }
 
     fn draw(&mut self, ctx: &mut miniquad::Context) {
+        let start = Instant::now();
+        let mut sample = FrameSample{
+            frame: self.frames,
+            frame_time: self.last_draw.map_or(0.0, |x| (start - x).as_secs_f32()),
+            gpu_time: self.gpu_timer.as_mut().and_then(|x| x.poll()),
+            ..Default::default()
+        };
+        self.last_draw = Some(start);
+        self.frames += 1;
+
         if self.scene.dirty(){
-            let rom = self.backend.get_scene_rom();
-            let mut serializer = SceneSerializer::new(rom);
+            // Serialized aside so a scene that doesn't fit never replaces the rom with a truncated one
+            let mut rom = [0; MAX_ROM_SIZE];
+            let mut serializer = SceneSerializer::new(&mut rom);
             self.scene.serialize(&mut serializer);
+            let (animated, words, overflowed) = (serializer.is_animated(), serializer.position(), serializer.overflowed());
+            if overflowed {
+                eprintln!("The scene doesn't fit in the {} words of the scene rom, keeping the previous scene", MAX_ROM_SIZE);
+            }
+            else{
+                self.backend.get_scene_rom().copy_from_slice(&rom);
+                self.scene_animated = animated;
+                sample.rom_words = words as u32;
+                self.backend.reset_accumulation();
+            }
             self.scene.mark_clean();
+            sample.serialize_time = start.elapsed().as_secs_f32();
+        }
+        // Scenes that move on their own never hold still long enough to accumulate samples
+        if self.scene_animated || self.has_animated_methods(){
+            self.backend.reset_accumulation();
         }
         let elapsed = self.timer.elapsed().as_secs_f32();
         self.backend.set_elapsed(elapsed);
-        if elapsed - self.old > 1.0{
-            println!("{}: {}",(elapsed - self.old),self.frames);
-            self.frames = 0;
-            self.old = elapsed;
+        self.backend.set_frame_index(sample.frame as u32);
+
+        if let Some(timer) = self.gpu_timer.as_mut(){
+            timer.begin();
         }
-        self.frames += 1;
         self.backend.render(ctx);
+
+        // The backends leave the depth of the scene in the default framebuffer
+        ctx.begin_default_pass(PassAction::Nothing);
+        unsafe{ self.app.assume_init_mut().draw_meshes(ctx); }
+        ctx.end_render_pass();
+        if let Some(timer) = self.gpu_timer.as_mut(){
+            timer.end();
+        }
+
+        sample.cpu_time = start.elapsed().as_secs_f32();
+        if let Err(e) = self.stats.push(sample){
+            eprintln!("Stopped exporting frame stats: {}", e);
+        }
+        unsafe{ self.app.assume_init_mut().frame_finished(&self.stats); }
+
+        ctx.commit_frame();
     }
 
     fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
@@ -223,6 +1102,17 @@ pub trait App<S: Scene, B: RayMarcherBackend> {
         where Self: Sized;
     fn update(&mut self,scene: &mut S, backend: &mut B);
 
+    /// Draws ordinary meshes over the rendered scene, depth tested against it.
+    /// `camera::view_projection` gives the matrix matching the scene's camera.
+    fn draw_meshes(&mut self, _ctx: &mut Context){
+
+    }
+
+    /// Called after every frame with the stats including it.
+    fn frame_finished(&mut self, _stats: &FrameStats){
+
+    }
+
     fn key_down_event(&mut self, _ctx: &mut Context, _keycode: miniquad::KeyCode, _keymods: miniquad::KeyMods, _repeat: bool) {
         
     }
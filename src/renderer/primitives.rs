@@ -0,0 +1,186 @@
+use miniquad::UniformType;
+
+use super::{bvh::Aabb, methods::{DataDeserializer, DataEntry}, scene::{Serializeable, SceneSerializer}};
+
+/// Primitives shipped with the renderer.
+///
+/// Every primitive comes with `sdf_<name>`, `bound_<name>` and `color_<name>` methods.
+/// The sdf and bound methods take the same parameters, and the color method takes an albedo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Primitive{
+    /// `center`, `half_size`
+    Box,
+    /// `center`, `half_size`, `rounding`, the rounding is taken from inside the half size
+    RoundedBox,
+    /// `center`, `major_radius`, `minor_radius`, lying in the xz plane
+    Torus,
+    /// `center`, `half_height`, `radius`, along the y axis
+    Capsule,
+    /// `center`, `half_height`, `radius`, along the y axis
+    Cylinder,
+    /// `center`, `half_height`, `radius`, base radius at the bottom and the tip at the top
+    Cone,
+    /// `center`, `radii`
+    Ellipsoid,
+    /// `center`, `size`, distance from the center to the corners
+    Octahedron,
+    /// `center`, `radius`, `half_height`, along the y axis
+    HexPrism,
+    /// `start`, `end`, `radius`
+    LineSegment,
+}
+
+impl Primitive{
+    pub const ALL: [Primitive;10] = [
+        Primitive::Box,
+        Primitive::RoundedBox,
+        Primitive::Torus,
+        Primitive::Capsule,
+        Primitive::Cylinder,
+        Primitive::Cone,
+        Primitive::Ellipsoid,
+        Primitive::Octahedron,
+        Primitive::HexPrism,
+        Primitive::LineSegment,
+    ];
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            Primitive::Box => "box",
+            Primitive::RoundedBox => "rounded_box",
+            Primitive::Torus => "torus",
+            Primitive::Capsule => "capsule",
+            Primitive::Cylinder => "cylinder",
+            Primitive::Cone => "cone",
+            Primitive::Ellipsoid => "ellipsoid",
+            Primitive::Octahedron => "octahedron",
+            Primitive::HexPrism => "hex_prism",
+            Primitive::LineSegment => "segment",
+        }
+    }
+
+    pub fn source(&self) -> &'static str{
+        match self{
+            Primitive::Box => include_str!("../../sdf/box.glsl"),
+            Primitive::RoundedBox => include_str!("../../sdf/rounded_box.glsl"),
+            Primitive::Torus => include_str!("../../sdf/torus.glsl"),
+            Primitive::Capsule => include_str!("../../sdf/capsule.glsl"),
+            Primitive::Cylinder => include_str!("../../sdf/cylinder.glsl"),
+            Primitive::Cone => include_str!("../../sdf/cone.glsl"),
+            Primitive::Ellipsoid => include_str!("../../sdf/ellipsoid.glsl"),
+            Primitive::Octahedron => include_str!("../../sdf/octahedron.glsl"),
+            Primitive::HexPrism => include_str!("../../sdf/hex_prism.glsl"),
+            Primitive::LineSegment => include_str!("../../sdf/segment.glsl"),
+        }
+    }
+
+    /// Parameters shared by the sdf and bound method.
+    pub fn deserializer(&self) -> DataDeserializer{
+        let float1 = |name: &str| DataEntry{ name: name.into(), type_: UniformType::Float1 };
+        let float3 = |name: &str| DataEntry{ name: name.into(), type_: UniformType::Float3 };
+        let entries = match self{
+            Primitive::Box => vec![float3("center"), float3("half_size")],
+            Primitive::RoundedBox => vec![float3("center"), float3("half_size"), float1("rounding")],
+            Primitive::Torus => vec![float3("center"), float1("major_radius"), float1("minor_radius")],
+            Primitive::Capsule | Primitive::Cylinder | Primitive::Cone => vec![float3("center"), float1("half_height"), float1("radius")],
+            Primitive::Ellipsoid => vec![float3("center"), float3("radii")],
+            Primitive::Octahedron => vec![float3("center"), float1("size")],
+            Primitive::HexPrism => vec![float3("center"), float1("radius"), float1("half_height")],
+            Primitive::LineSegment => vec![float3("start"), float3("end"), float1("radius")],
+        };
+        DataDeserializer::new(entries)
+    }
+
+    pub fn tex_deserializer(&self) -> DataDeserializer{
+        DataDeserializer::new(vec![
+            DataEntry{ name: "color".into(), type_: UniformType::Float3 }
+        ])
+    }
+
+    /// Number of floats the sdf and bound method read.
+    pub fn param_len(&self) -> usize{
+        self.deserializer().data_len()
+    }
+
+    /// Box around the primitive, `params` are laid out as described on `Primitive`.
+    pub fn aabb(&self, params: &[f32]) -> Aabb{
+        let center = [params[0], params[1], params[2]];
+        match self{
+            Primitive::Box | Primitive::RoundedBox => Aabb::from_center(center, [params[3], params[4], params[5]]),
+            Primitive::Torus => {
+                let outer = params[3] + params[4];
+                Aabb::from_center(center, [outer, params[4], outer])
+            },
+            Primitive::Capsule => Aabb::from_center(center, [params[4], params[3] + params[4], params[4]]),
+            Primitive::Cylinder | Primitive::Cone => Aabb::from_center(center, [params[4], params[3], params[4]]),
+            Primitive::Ellipsoid => Aabb::from_center(center, [params[3], params[4], params[5]]),
+            Primitive::Octahedron => Aabb::from_radius(center, params[3]),
+            Primitive::HexPrism => {
+                // Distance to the corners
+                let outer = params[3] * 1.1547005;
+                Aabb::from_center(center, [outer, params[4], outer])
+            },
+            Primitive::LineSegment => {
+                let end = [params[3], params[4], params[5]];
+                Aabb::around([center, end].into_iter()).expanded([params[6];3])
+            },
+        }
+    }
+}
+
+/// Method ids a primitive was registered with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PrimitiveIds{
+    pub bound: u32,
+    pub sdf: u32,
+    pub tex: u32
+}
+
+/// Ids of every primitive registered through `Renderer::register_primitive_library`.
+#[derive(Clone, Debug)]
+pub struct PrimitiveLibrary{
+    pub(crate) ids: Vec<(Primitive, PrimitiveIds)>
+}
+
+impl PrimitiveLibrary{
+    pub fn ids(&self, primitive: Primitive) -> PrimitiveIds{
+        self.ids.iter()
+            .find(|(x,_)| *x == primitive)
+            .map(|(_,ids)| *ids)
+            .unwrap_or_else(|| panic!("Primitive {:?} is not registered", primitive))
+    }
+
+    /// Creates an instance using the default color method, `params` are laid out as described on `Primitive`.
+    pub fn instance(&self, primitive: Primitive, params: &[f32], color: [f32;3]) -> PrimitiveInstance{
+        assert_eq!(params.len(), primitive.param_len(), "Wrong number of parameters for {:?}", primitive);
+        PrimitiveInstance{
+            primitive,
+            ids: self.ids(primitive),
+            params: params.to_vec(),
+            color
+        }
+    }
+}
+
+pub struct PrimitiveInstance{
+    primitive: Primitive,
+    ids: PrimitiveIds,
+    params: Vec<f32>,
+    color: [f32;3]
+}
+
+impl Serializeable for PrimitiveInstance{
+    fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
+        serializer.write_value(self.ids.bound);
+        self.params[..].serialize(serializer);
+        serializer.write_value(self.ids.sdf);
+        self.params[..].serialize(serializer);
+        serializer.write_value(self.primitive.tex_deserializer().data_len() as u32 + 1);
+        serializer.write_value(self.ids.tex);
+        self.color.serialize(serializer);
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        Some(self.primitive.aabb(&self.params))
+    }
+}
@@ -1,4 +1,20 @@
-use std::{ptr::NonNull, num::NonZeroU32};
+use std::num::NonZeroU32;
+
+use super::{bvh::{Aabb, serialize_bvh}, math::Vec3};
+
+/// Rom opcodes share the slot of the bound id, so they are negative to never collide with a registered method.
+pub const OP_GROUP_BEGIN: i32 = -1;
+pub const OP_GROUP_END: i32 = -2;
+pub const OP_TRANSFORM: i32 = -3;
+pub const OP_DOMAIN: i32 = -4;
+pub const OP_ANIMATE: i32 = -5;
+pub const OP_BVH_NODE: i32 = -6;
+
+/// Most keys an `Animation::Keyframes` track may have, so the shader can keep its search loop bounded.
+pub const MAX_KEYFRAMES: usize = 32;
+
+/// Maximum nesting of groups, including the implicit union around the whole scene.
+pub const MAX_GROUP_DEPTH: usize = 8;
 
 pub trait Scene : Serializeable{
     fn dirty(&self) -> bool;
@@ -12,10 +28,379 @@ pub trait SceneInstance{
     fn get_bound_data(&self) -> Vec<u32>;
     fn get_sdf_data(&self) -> Vec<u32>;
     fn get_tex_data(&self) -> Vec<u32>;
+    fn get_transform(&self) -> Option<Transform>{
+        None
+    }
+    /// Modifiers applied in order after the transform has moved into local space.
+    fn get_domain_modifiers(&self) -> Vec<DomainModifier>{
+        Vec::new()
+    }
+    /// Motion applied in order after the transform, before the domain modifiers.
+    fn get_animations(&self) -> Vec<Animation>{
+        Vec::new()
+    }
+    /// World space box the instance stays inside of, with its transform and animations applied.
+    fn get_aabb(&self) -> Option<Aabb>{
+        None
+    }
+}
+
+/// Placement of an instance or group, applied as scale, then rotation, then translation.
+///
+/// The shader moves the sample position into the local space of the instance before calling
+/// its bound and sdf methods, and scales the distance back by the smallest scale axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform{
+    pub translation: [f32;3],
+    /// Unit quaternion as `[x, y, z, w]`.
+    pub rotation: [f32;4],
+    pub scale: [f32;3]
+}
+
+fn assert_positive_scale(scale: [f32;3]){
+    assert!(scale.iter().all(|x| *x > 0.0), "Transform scale components have to be positive, got {:?}", scale);
+}
+
+impl Transform{
+    pub fn translate(translation: [f32;3]) -> Self{
+        Self{
+            translation,
+            ..Default::default()
+        }
+    }
+
+    pub fn rotate(axis: [f32;3], angle: f32) -> Self{
+        Self::default().with_rotation(axis, angle)
+    }
+
+    /// Every component has to be positive, the shader divides by them and scales distances by the smallest.
+    pub fn scale(scale: [f32;3]) -> Self{
+        assert_positive_scale(scale);
+        Self{
+            scale,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_translation(mut self, translation: [f32;3]) -> Self{
+        self.translation = translation;
+        self
+    }
+
+    /// Sets the rotation to `angle` radians around `axis`, a zero axis doesn't rotate.
+    pub fn with_rotation(mut self, axis: [f32;3], angle: f32) -> Self{
+        let len = (axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2]).sqrt();
+        if len == 0.0 {
+            self.rotation = [0.0, 0.0, 0.0, 1.0];
+            return self;
+        }
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        let s = sin / len;
+        self.rotation = [axis[0] * s, axis[1] * s, axis[2] * s, cos];
+        self
+    }
+
+    /// Same requirements as `Transform::scale`.
+    pub fn with_scale(mut self, scale: [f32;3]) -> Self{
+        assert_positive_scale(scale);
+        self.scale = scale;
+        self
+    }
+
+    /// Moves a point from the local space of the transform out to the space it is placed in.
+    pub fn apply(&self, point: [f32;3]) -> [f32;3]{
+        let q = Vec3::from([self.rotation[0], self.rotation[1], self.rotation[2]]);
+        let v = Vec3::from(point) * Vec3::from(self.scale);
+        let rotated = v + q.cross(q.cross(v) + v * self.rotation[3]) * 2.0;
+        (rotated + Vec3::from(self.translation)).into()
+    }
+}
+
+impl Default for Transform{
+    fn default() -> Self {
+        Self{
+            translation: [0.0;3],
+            rotation: [0.0,0.0,0.0,1.0],
+            scale: [1.0;3]
+        }
+    }
+}
+
+impl Serializeable for Transform{
+    fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
+        serializer.write_value(OP_TRANSFORM as u32);
+        self.translation.serialize(serializer);
+        self.rotation.serialize(serializer);
+        self.scale.serialize(serializer);
+    }
+}
+
+/// Surface description matching the `Material` struct of the scene shader.
+///
+/// Serializes in the layout expected by `DataDeserializer::material`,
+/// so it can be written as the data of a tex method taking every material field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material{
+    pub albedo: [f32;3],
+    pub roughness: f32,
+    pub metallic: f32,
+    pub emission: [f32;3],
+    pub opacity: f32,
+    pub ior: f32
+}
+
+impl Material{
+    pub fn diffuse(albedo: [f32;3]) -> Self{
+        Self{
+            albedo,
+            ..Default::default()
+        }
+    }
+
+    pub fn mirror(albedo: [f32;3]) -> Self{
+        Self{
+            albedo,
+            roughness: 0.0,
+            metallic: 1.0,
+            ..Default::default()
+        }
+    }
+
+    pub fn glass(albedo: [f32;3], ior: f32) -> Self{
+        Self{
+            albedo,
+            roughness: 0.0,
+            opacity: 0.0,
+            ior,
+            ..Default::default()
+        }
+    }
+
+    pub fn emissive(emission: [f32;3]) -> Self{
+        Self{
+            albedo: [0.0;3],
+            emission,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for Material{
+    fn default() -> Self {
+        Self{
+            albedo: [1.0;3],
+            roughness: 1.0,
+            metallic: 0.0,
+            emission: [0.0;3],
+            opacity: 1.0,
+            ior: 1.5
+        }
+    }
+}
+
+impl Serializeable for Material{
+    fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
+        self.albedo.serialize(serializer);
+        self.roughness.serialize(serializer);
+        self.metallic.serialize(serializer);
+        self.emission.serialize(serializer);
+        self.opacity.serialize(serializer);
+        self.ior.serialize(serializer);
+    }
+}
+
+/// Remaps the sample position before the sdf method of an instance is called.
+///
+/// Instances below a domain modifier are always evaluated, as their bound methods can no longer
+/// tell where the copies are. Twist and bend are applied around the local y and z axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DomainModifier{
+    /// Repeats space every `period` units, an axis with a period of 0 is left alone.
+    Repeat{
+        period: [f32;3]
+    },
+    /// Like `Repeat`, but only `limit` copies to each side of the original.
+    RepeatLimited{
+        period: [f32;3],
+        limit: [f32;3]
+    },
+    /// Mirrors the negative half of every selected axis onto the positive one.
+    Mirror{
+        axes: [bool;3]
+    },
+    /// Rotates around the y axis by `strength` radians per unit of height.
+    Twist{
+        strength: f32
+    },
+    /// Bends the x axis upwards by `strength` radians per unit of length.
+    Bend{
+        strength: f32
+    },
+}
+
+impl DomainModifier{
+    pub fn code(&self) -> u32{
+        match self{
+            DomainModifier::Repeat { .. } => 0,
+            DomainModifier::RepeatLimited { .. } => 1,
+            DomainModifier::Mirror { .. } => 2,
+            DomainModifier::Twist { .. } => 3,
+            DomainModifier::Bend { .. } => 4,
+        }
+    }
+}
+
+impl DomainModifier{
+    /// Box around everything the modifier can make of a child inside `aabb`, `None` for endless repetition.
+    pub fn aabb(&self, aabb: Aabb) -> Option<Aabb>{
+        match self{
+            DomainModifier::Repeat { .. } => None,
+            DomainModifier::RepeatLimited { period, limit } => {
+                Some(aabb.expanded([0, 1, 2].map(|axis| period[axis] * limit[axis])))
+            },
+            DomainModifier::Mirror { axes } => {
+                let mut mirrored = aabb;
+                for axis in (0..3).filter(|x| axes[*x]){
+                    let extent = aabb.min[axis].abs().max(aabb.max[axis].abs());
+                    mirrored.min[axis] = -extent;
+                    mirrored.max[axis] = extent;
+                }
+                Some(mirrored)
+            },
+            // Both rotate around an axis, keeping the distance to it
+            DomainModifier::Twist { .. } => {
+                let radius = aabb.corners().iter().map(|x| x[0].hypot(x[2])).fold(0.0, f32::max);
+                Some(Aabb::new([-radius, aabb.min[1], -radius], [radius, aabb.max[1], radius]))
+            },
+            DomainModifier::Bend { .. } => {
+                let radius = aabb.corners().iter().map(|x| x[0].hypot(x[1])).fold(0.0, f32::max);
+                Some(Aabb::new([-radius, -radius, aabb.min[2]], [radius, radius, aabb.max[2]]))
+            },
+        }
+    }
+}
+
+impl Serializeable for DomainModifier{
+    fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
+        serializer.write_value(OP_DOMAIN as u32);
+        serializer.write_value(self.code());
+        match self{
+            DomainModifier::Repeat { period } => {
+                serializer.write_value(3);
+                period.serialize(serializer);
+            },
+            DomainModifier::RepeatLimited { period, limit } => {
+                serializer.write_value(6);
+                period.serialize(serializer);
+                limit.serialize(serializer);
+            },
+            DomainModifier::Mirror { axes } => {
+                serializer.write_value(3);
+                axes.map(|x| if x { 1.0f32 } else { 0.0 }).serialize(serializer);
+            },
+            DomainModifier::Twist { strength } | DomainModifier::Bend { strength } => {
+                serializer.write_value(1);
+                strength.serialize(serializer);
+            },
+        }
+    }
+}
+
+/// Rigid motion the shader evaluates from `elapsed_time`, so moving instances don't need a new rom every frame.
+///
+/// Like a `Transform` it moves the sample position into the local space of what it wraps,
+/// rotations turn around the local origin.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Animation{
+    /// Moves back and forth along `amplitude`, `frequency` times a second, starting `phase` radians into the swing.
+    Oscillate{
+        amplitude: [f32;3],
+        frequency: f32,
+        phase: f32
+    },
+    /// Rotates around `axis` by `speed` radians a second.
+    Spin{
+        axis: [f32;3],
+        speed: f32
+    },
+    /// Translation interpolated linearly between `(time, translation)` keys sorted by time.
+    /// Before the first key and after the last one the nearest key is held, unless the track loops.
+    Keyframes{
+        keys: Vec<(f32, [f32;3])>,
+        looping: bool
+    },
+}
+
+impl Animation{
+    pub fn code(&self) -> u32{
+        match self{
+            Animation::Oscillate { .. } => 0,
+            Animation::Spin { .. } => 1,
+            Animation::Keyframes { .. } => 2,
+        }
+    }
+}
+
+impl Animation{
+    /// Box around everywhere the animation moves a child inside `aabb`.
+    pub fn aabb(&self, aabb: Aabb) -> Aabb{
+        match self{
+            Animation::Oscillate { amplitude, .. } => aabb.expanded(amplitude.map(f32::abs)),
+            Animation::Spin { .. } => Aabb::from_radius([0.0;3], aabb.max_distance_from([0.0;3])),
+            // Linear interpolation never leaves the box around the keys
+            Animation::Keyframes { keys, .. } => keys.iter()
+                .map(|(_, translation)| aabb.translated(*translation))
+                .reduce(|a, b| a.union(&b))
+                .unwrap_or(aabb),
+        }
+    }
+}
+
+impl Serializeable for Animation{
+    fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
+        serializer.write_value(OP_ANIMATE as u32);
+        serializer.write_value(self.code());
+        serializer.mark_animated();
+        match self{
+            Animation::Oscillate { amplitude, frequency, phase } => {
+                serializer.write_value(5);
+                amplitude.serialize(serializer);
+                frequency.serialize(serializer);
+                phase.serialize(serializer);
+            },
+            Animation::Spin { axis, speed } => {
+                let len = (axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2]).sqrt();
+                // Like `Transform::with_rotation`, a zero axis doesn't rotate
+                let (axis, speed) = if len == 0.0 { ([0.0;3], 0.0) } else { (axis.map(|x| x / len), *speed) };
+                serializer.write_value(4);
+                axis.serialize(serializer);
+                speed.serialize(serializer);
+            },
+            Animation::Keyframes { keys, looping } => {
+                assert!(!keys.is_empty() && keys.len() <= MAX_KEYFRAMES, "Keyframe tracks need between 1 and {} keys", MAX_KEYFRAMES);
+                serializer.write_value(2 + keys.len() as u32 * 4);
+                serializer.write_value(keys.len() as u32);
+                serializer.write_value(*looping as u32);
+                for (time, translation) in keys{
+                    time.serialize(serializer);
+                    translation.serialize(serializer);
+                }
+            },
+        }
+    }
 }
 
 impl Serializeable for dyn SceneInstance{
+    fn aabb(&self) -> Option<Aabb> {
+        self.get_aabb()
+    }
+
     fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
+        if let Some(transform) = self.get_transform(){
+            transform.serialize(serializer);
+        }
+        self.get_animations().serialize(serializer);
+        self.get_domain_modifiers().serialize(serializer);
         match self.get_bound_id(){
             Some(x) => {
                 serializer.write_value(x.get());
@@ -41,6 +426,15 @@ impl Serializeable for dyn SceneInstance{
 
 pub trait Serializeable{
     fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>);
+    /// Box the serialized instances stay inside of, `None` if they are unbounded or it isn't known.
+    /// Top level instances with a box are sorted into the bvh of `SimpleScene`.
+    fn aabb(&self) -> Option<Aabb>{
+        None
+    }
+    /// Number of instances `serialize` writes.
+    fn instance_count(&self) -> usize{
+        1
+    }
 }
 
 impl Serializeable for f32{
@@ -59,6 +453,14 @@ impl Serializeable for Box<dyn Serializeable> {
     fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
         self.as_ref().serialize(serializer);
     }
+
+    fn aabb(&self) -> Option<Aabb> {
+        self.as_ref().aabb()
+    }
+
+    fn instance_count(&self) -> usize {
+        self.as_ref().instance_count()
+    }
 }
 
 impl<T: Serializeable> Serializeable for [T] {
@@ -67,35 +469,297 @@ impl<T: Serializeable> Serializeable for [T] {
             x.serialize(serializer);
         }
     }
+
+    fn aabb(&self) -> Option<Aabb> {
+        self.iter()
+            .map(|x| x.aabb())
+            .reduce(|a, b| a.zip(b).map(|(a, b)| a.union(&b)))
+            .flatten()
+    }
+
+    fn instance_count(&self) -> usize {
+        self.iter().map(|x| x.instance_count()).sum()
+    }
+}
+
+/// Boolean operation a group applies between its members.
+///
+/// Members are folded in order, so for the subtractions the first member is the base
+/// and every following member is carved out of it. The smooth variants take the blend radius.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsgOp{
+    Union,
+    Subtract,
+    Intersect,
+    SmoothUnion(f32),
+    SmoothSubtract(f32),
+    SmoothIntersect(f32),
+}
+
+impl CsgOp{
+    pub fn code(&self) -> u32{
+        match self{
+            CsgOp::Union => 0,
+            CsgOp::Subtract => 1,
+            CsgOp::Intersect => 2,
+            CsgOp::SmoothUnion(_) => 3,
+            CsgOp::SmoothSubtract(_) => 4,
+            CsgOp::SmoothIntersect(_) => 5,
+        }
+    }
+
+    pub fn blend_radius(&self) -> f32{
+        match self{
+            CsgOp::SmoothUnion(k) | CsgOp::SmoothSubtract(k) | CsgOp::SmoothIntersect(k) => *k,
+            _ => 0.0,
+        }
+    }
+
+    /// The op written to the rom, smooth ops without a positive radius fall back to the hard ones
+    /// since the blend divides by the radius.
+    pub fn serialized(&self) -> CsgOp{
+        match *self{
+            CsgOp::SmoothUnion(k) if k <= 0.0 => CsgOp::Union,
+            CsgOp::SmoothSubtract(k) if k <= 0.0 => CsgOp::Subtract,
+            CsgOp::SmoothIntersect(k) if k <= 0.0 => CsgOp::Intersect,
+            op => op,
+        }
+    }
+}
+
+/// A node in a scene tree, either a single instance, a group combining its children,
+/// or a transform, animation or domain modifier applied to the node it wraps.
+///
+/// Wrapping applies object space operations in call order, so `node.modified(twist).modified(repeat)`
+/// repeats the twisted node.
+///
+/// Bounds of members inside smooth groups should be grown by the blend radius,
+/// as the blended surface reaches past the members themselves.
+///
+/// Boxes for the bvh are worked out from the children, `bounded` overrides them with a known box.
+pub enum SceneNode{
+    Instance(Box<dyn Serializeable>),
+    Group{
+        op: CsgOp,
+        children: Vec<SceneNode>
+    },
+    Transformed{
+        transform: Transform,
+        child: Box<SceneNode>
+    },
+    Modified{
+        modifier: DomainModifier,
+        child: Box<SceneNode>
+    },
+    Animated{
+        animation: Animation,
+        child: Box<SceneNode>
+    },
+    Bounded{
+        aabb: Aabb,
+        child: Box<SceneNode>
+    }
+}
+
+impl SceneNode{
+    pub fn instance(x: impl Serializeable + 'static) -> Self{
+        SceneNode::Instance(Box::new(x))
+    }
+
+    pub fn group(op: CsgOp, children: Vec<SceneNode>) -> Self{
+        SceneNode::Group{
+            op,
+            children
+        }
+    }
+
+    pub fn union(children: Vec<SceneNode>) -> Self{
+        Self::group(CsgOp::Union, children)
+    }
+
+    pub fn subtract(base: SceneNode, cutters: Vec<SceneNode>) -> Self{
+        Self::group(CsgOp::Subtract, std::iter::once(base).chain(cutters).collect())
+    }
+
+    pub fn intersect(children: Vec<SceneNode>) -> Self{
+        Self::group(CsgOp::Intersect, children)
+    }
+
+    pub fn transformed(self, transform: Transform) -> Self{
+        SceneNode::Transformed{
+            transform,
+            child: Box::new(self)
+        }
+    }
+
+    pub fn modified(self, modifier: DomainModifier) -> Self{
+        SceneNode::Modified{
+            modifier,
+            child: Box::new(self)
+        }
+    }
+
+    pub fn animated(self, animation: Animation) -> Self{
+        SceneNode::Animated{
+            animation,
+            child: Box::new(self)
+        }
+    }
+
+    /// Sets the box the node stays inside of, in the space the node is placed in.
+    pub fn bounded(self, aabb: Aabb) -> Self{
+        SceneNode::Bounded{
+            aabb,
+            child: Box::new(self)
+        }
+    }
+
+    pub fn push(&mut self, child: SceneNode){
+        match self{
+            SceneNode::Group { children, .. } => children.push(child),
+            SceneNode::Transformed { child: inner, .. } | SceneNode::Modified { child: inner, .. } | SceneNode::Animated { child: inner, .. } | SceneNode::Bounded { child: inner, .. } => inner.push(child),
+            SceneNode::Instance(_) => panic!("Cannot add children to an instance node"),
+        }
+    }
+}
+
+impl Serializeable for SceneNode{
+    fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
+        match self{
+            SceneNode::Instance(x) => x.serialize(serializer),
+            SceneNode::Group { op, children } => {
+                serializer.begin_group(*op);
+                children[..].serialize(serializer);
+                serializer.end_group();
+            },
+            SceneNode::Transformed { transform, child } => {
+                transform.serialize(serializer);
+                child.serialize(serializer);
+            },
+            SceneNode::Modified { modifier, child } => {
+                modifier.serialize(serializer);
+                child.serialize(serializer);
+            },
+            SceneNode::Animated { animation, child } => {
+                animation.serialize(serializer);
+                child.serialize(serializer);
+            },
+            SceneNode::Bounded { child, .. } => child.serialize(serializer),
+        }
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        match self{
+            SceneNode::Instance(x) => x.aabb(),
+            SceneNode::Group { op, children } => {
+                let aabb = match op{
+                    // Everything left lies inside the base
+                    CsgOp::Subtract | CsgOp::SmoothSubtract(_) => children.first()?.aabb(),
+                    // and inside every member, so any known box will do
+                    CsgOp::Intersect | CsgOp::SmoothIntersect(_) => children.iter().find_map(|x| x.aabb()),
+                    CsgOp::Union | CsgOp::SmoothUnion(_) => children[..].aabb(),
+                }?;
+                Some(aabb.expanded([op.blend_radius();3]))
+            },
+            SceneNode::Transformed { transform, child } => child.aabb().map(|x| x.transformed(transform)),
+            SceneNode::Modified { modifier, child } => modifier.aabb(child.aabb()?),
+            SceneNode::Animated { animation, child } => child.aabb().map(|x| animation.aabb(x)),
+            SceneNode::Bounded { aabb, .. } => Some(*aabb),
+        }
+    }
+
+    fn instance_count(&self) -> usize {
+        match self{
+            SceneNode::Instance(x) => x.instance_count(),
+            SceneNode::Group { children, .. } => children[..].instance_count(),
+            SceneNode::Transformed { child, .. } | SceneNode::Modified { child, .. } | SceneNode::Animated { child, .. } | SceneNode::Bounded { child, .. } => child.instance_count(),
+        }
+    }
 }
 
 pub struct SceneSerializer<'a>{
     out: &'a mut[u32],
-    index: usize
+    index: usize,
+    depth: usize,
+    animated: bool,
+    overflowed: bool,
 }
 
 impl<'a> SceneSerializer<'a> {
     pub fn new(out: &'a mut [u32]) -> Self{
         Self{
             index: 0,
+            depth: 0,
+            animated: false,
+            overflowed: false,
             out
         }
     }
 
+    /// Records that the scene moves on its own, so backends don't treat consecutive frames as the same image.
+    pub fn mark_animated(&mut self){
+        self.animated = true;
+    }
+
+    pub fn is_animated(&self) -> bool{
+        self.animated
+    }
+
+    pub fn begin_group(&mut self, op: CsgOp){
+        assert!(self.depth + 1 < MAX_GROUP_DEPTH, "Scene groups can be nested at most {} levels deep", MAX_GROUP_DEPTH - 1);
+        self.depth += 1;
+        let op = op.serialized();
+        self.write_value(OP_GROUP_BEGIN as u32);
+        self.write_value(op.code());
+        self.write_value(op.blend_radius().to_bits());
+    }
+
+    pub fn end_group(&mut self){
+        assert!(self.depth > 0, "end_group called without a matching begin_group");
+        self.depth -= 1;
+        self.write_value(OP_GROUP_END as u32);
+    }
+
+    /// Index the next value is written to.
+    pub fn position(&self) -> usize{
+        self.index
+    }
+
+    /// Overwrites an already written value, for records whose size is only known after their contents.
+    /// Values dropped because the rom overflowed can't be patched, the overflow is reported by `overflowed` already.
+    pub fn patch(&mut self, index: usize, value: u32){
+        if index < self.index{
+            self.out[index] = value;
+        }
+        else{
+            assert!(self.overflowed, "Patched index {} was never written, only {} values were", index, self.index);
+        }
+    }
+
     pub fn has_space_for(&mut self, els: usize) -> bool{
-        self.index + els <= 1024
+        self.index + els <= self.out.len()
+    }
+
+    /// Whether a write was dropped because the scene didn't fit, the rom then holds a truncated scene.
+    pub fn overflowed(&self) -> bool{
+        self.overflowed
     }
 
     pub fn write_value(&mut self, value: u32){
         if self.has_space_for(1){
             self.out[self.index] = value;
             self.index+=1;    
+        }else{
+            self.overflowed = true;
         }
     }
 
     pub fn write_values(&mut self, value: &[u32]){
         if self.has_space_for(value.len()){
-            self.out[self.index..].copy_from_slice(value);
+            self.out[self.index..self.index + value.len()].copy_from_slice(value);
+            self.index += value.len();
+        }else{
+            self.overflowed = true;
         }
     }
 }
@@ -113,6 +777,7 @@ impl SimpleScene{
         }
     }
 
+    /// Adds an instance, or a whole `SceneNode` tree, to the union making up the scene.
     pub fn add_instance(&mut self, x: impl Serializeable + 'static){
         self.objects.push(Box::new(x));
     }
@@ -122,11 +787,27 @@ impl SimpleScene{
     }
 }
 
+impl Default for SimpleScene{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Serializeable for SimpleScene{
+    /// Instances without a box come first, the rest are written as a bvh.
     fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
-        self.objects.iter().for_each(|x|{
-            x.serialize(serializer);
-        });
+        let mut bounded = Vec::new();
+        for x in self.objects.iter(){
+            match x.aabb(){
+                Some(aabb) => bounded.push((x.as_ref(), aabb)),
+                None => x.serialize(serializer),
+            }
+        }
+        serialize_bvh(&mut bounded, serializer);
+    }
+
+    fn instance_count(&self) -> usize {
+        self.objects[..].instance_count()
     }
 }
 
@@ -0,0 +1,223 @@
+//! Plain text scene descriptions, for rendering scenes without writing an app.
+//!
+//! Every line holds one statement, `#` starts a comment:
+//!
+//! ```text
+//! camera 0 1 -2
+//! background gradient 0.8 0.85 0.9 0.35 0.5 0.8
+//! fog 0.02
+//! bounces 2
+//!
+//! box 0 -1.5 6 10 0.5 10 color 0.5 0.5 0.5
+//! smooth_subtract 0.1 {
+//!     ellipsoid 0 0 6 1 1 1 color 1 0 0
+//!     ellipsoid 0 0.5 5.2 0.6 0.6 0.6
+//! }
+//! translate 2 0 6 {
+//!     rotate 0 1 0 0.5 {
+//!         box 0 0 0 0.5 0.5 0.5 color 0 1 0
+//!     }
+//! }
+//! ```
+//!
+//! Primitives are written with the name and parameters from `Primitive`, optionally followed by `color r g b`.
+//! Blocks are csg groups (`union`, `subtract`, `intersect` and their `smooth_` versions taking a blend radius)
+//! or transforms of their contents (`translate x y z`, `rotate ax ay az angle`, `scale x y z`).
+//! Backgrounds are `solid r g b`, `gradient` with the horizon and zenith colors, or `sky` with the sun direction and color.
+
+use std::fmt;
+
+use super::{
+    background::Background,
+    primitives::{Primitive, PrimitiveLibrary},
+    scene::{SimpleScene, SceneNode, CsgOp, Transform},
+};
+
+const DEFAULT_COLOR: [f32;3] = [0.8;3];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SceneFileError{
+    /// Line the error was found on, starting at 1
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for SceneFileError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SceneFileError{}
+
+/// A parsed scene description, settings left out of the file keep the renderer defaults.
+pub struct SceneFile{
+    pub camera: [f32;3],
+    pub background: Option<Background>,
+    pub fog_density: Option<f32>,
+    pub bounce_depth: Option<u32>,
+    pub nodes: Vec<SceneNode>
+}
+
+enum Block{
+    Group(CsgOp),
+    Transform(Transform),
+}
+
+struct OpenBlock{
+    block: Block,
+    line: usize,
+    children: Vec<SceneNode>
+}
+
+impl SceneFile{
+    /// Parses a description, creating the primitives from a library registered with the renderer that will draw them.
+    pub fn parse(source: &str, library: &PrimitiveLibrary) -> Result<Self, SceneFileError>{
+        let mut file = SceneFile{
+            camera: [0.0;3],
+            background: None,
+            fog_density: None,
+            bounce_depth: None,
+            nodes: Vec::new()
+        };
+        let mut open: Vec<OpenBlock> = Vec::new();
+
+        for (index, text) in source.lines().enumerate(){
+            let line = index + 1;
+            let error = |message: String| SceneFileError{ line, message };
+            let tokens = text.split('#').next().unwrap_or("").split_whitespace().collect::<Vec<_>>();
+            let Some((keyword, args)) = tokens.split_first() else {
+                continue;
+            };
+
+            if *keyword == "}" {
+                if !args.is_empty() {
+                    return Err(error("Expected nothing after '}'".into()));
+                }
+                let closed = open.pop().ok_or_else(|| error("'}' without an open block".into()))?;
+                let node = match closed.block{
+                    Block::Group(op) => SceneNode::group(op, closed.children),
+                    Block::Transform(transform) => {
+                        let mut children = closed.children;
+                        let node = if children.len() == 1 { children.remove(0) } else { SceneNode::union(children) };
+                        node.transformed(transform)
+                    },
+                };
+                push_node(&mut open, &mut file.nodes, node);
+                continue;
+            }
+
+            if args.last() == Some(&"{") {
+                let args = parse_floats(&args[..args.len() - 1]).map_err(error)?;
+                let block = parse_block(keyword, &args).map_err(error)?;
+                open.push(OpenBlock{
+                    block,
+                    line,
+                    children: Vec::new()
+                });
+                continue;
+            }
+
+            match *keyword{
+                "camera" => file.camera = parse_vec3(args).map_err(error)?,
+                "fog" => file.fog_density = Some(parse_count(args, 1).map_err(error)?[0]),
+                "bounces" => file.bounce_depth = Some(args.first()
+                    .filter(|_| args.len() == 1)
+                    .and_then(|x| x.parse().ok())
+                    .ok_or_else(|| error("Expected one whole number of bounces".into()))?),
+                "background" => file.background = Some(parse_background(args).map_err(error)?),
+                name => {
+                    let primitive = Primitive::ALL.iter()
+                        .find(|x| x.name() == name)
+                        .ok_or_else(|| error(format!("Unknown statement '{}'", name)))?;
+                    let (params, color) = match args.iter().position(|x| *x == "color"){
+                        Some(split) => (&args[..split], parse_vec3(&args[split + 1..]).map_err(error)?),
+                        None => (args, DEFAULT_COLOR),
+                    };
+                    let params = parse_count(params, primitive.param_len())
+                        .map_err(|message| error(format!("{} for {}", message, name)))?;
+                    push_node(&mut open, &mut file.nodes, SceneNode::instance(library.instance(*primitive, &params, color)));
+                },
+            }
+        }
+
+        match open.last(){
+            Some(block) => Err(SceneFileError{ line: block.line, message: "Block is never closed".into() }),
+            None => Ok(file),
+        }
+    }
+
+    /// Moves the nodes into a scene, ready to be serialized.
+    pub fn take_scene(&mut self) -> SimpleScene{
+        let mut scene = SimpleScene::new();
+        for node in self.nodes.drain(..){
+            scene.add_instance(node);
+        }
+        scene.mark_dirty();
+        scene
+    }
+}
+
+fn push_node(open: &mut [OpenBlock], nodes: &mut Vec<SceneNode>, node: SceneNode){
+    match open.last_mut(){
+        Some(block) => block.children.push(node),
+        None => nodes.push(node),
+    }
+}
+
+fn parse_block(keyword: &str, args: &[f32]) -> Result<Block, String>{
+    let expect = |count: usize| if args.len() == count { Ok(()) } else { Err(format!("Expected {} numbers before '{{' of {}", count, keyword)) };
+    Ok(match keyword{
+        "union" => expect(0).map(|_| Block::Group(CsgOp::Union))?,
+        "subtract" => expect(0).map(|_| Block::Group(CsgOp::Subtract))?,
+        "intersect" => expect(0).map(|_| Block::Group(CsgOp::Intersect))?,
+        "smooth_union" => expect(1).map(|_| Block::Group(CsgOp::SmoothUnion(args[0])))?,
+        "smooth_subtract" => expect(1).map(|_| Block::Group(CsgOp::SmoothSubtract(args[0])))?,
+        "smooth_intersect" => expect(1).map(|_| Block::Group(CsgOp::SmoothIntersect(args[0])))?,
+        "translate" => expect(3).map(|_| Block::Transform(Transform::translate([args[0], args[1], args[2]])))?,
+        "rotate" => expect(4).map(|_| Block::Transform(Transform::rotate([args[0], args[1], args[2]], args[3])))?,
+        "scale" => {
+            expect(3)?;
+            if !args.iter().all(|x| *x > 0.0) {
+                return Err("Scale components have to be positive".into());
+            }
+            Block::Transform(Transform::scale([args[0], args[1], args[2]]))
+        },
+        _ => return Err(format!("Unknown block '{}'", keyword)),
+    })
+}
+
+fn parse_background(args: &[&str]) -> Result<Background, String>{
+    let (kind, args) = args.split_first().ok_or("Expected a background kind")?;
+    Ok(match *kind{
+        "solid" => Background::Solid(parse_vec3(args)?),
+        "gradient" => {
+            let values = parse_count(args, 6)?;
+            Background::Gradient{ horizon: [values[0], values[1], values[2]], zenith: [values[3], values[4], values[5]] }
+        },
+        "sky" => {
+            let values = parse_count(args, 6)?;
+            Background::Sky{ sun_direction: [values[0], values[1], values[2]], sun_color: [values[3], values[4], values[5]] }
+        },
+        _ => return Err(format!("Unknown background '{}'", kind)),
+    })
+}
+
+fn parse_floats(args: &[&str]) -> Result<Vec<f32>, String>{
+    args.iter()
+        .map(|x| x.parse::<f32>().map_err(|_| format!("'{}' is not a number", x)))
+        .collect()
+}
+
+fn parse_count(args: &[&str], count: usize) -> Result<Vec<f32>, String>{
+    let values = parse_floats(args)?;
+    if values.len() != count {
+        return Err(format!("Expected {} numbers, found {}", count, values.len()));
+    }
+    Ok(values)
+}
+
+fn parse_vec3(args: &[&str]) -> Result<[f32;3], String>{
+    let values = parse_count(args, 3)?;
+    Ok([values[0], values[1], values[2]])
+}
@@ -0,0 +1,292 @@
+//! Timings of the frames drawn by the `Renderer`, kept over a rolling window.
+
+use std::{collections::VecDeque, io::{self, Write}};
+
+use miniquad::gl;
+
+/// Frames kept by `FrameStats::default`, a few seconds at common refresh rates.
+pub const DEFAULT_STATS_WINDOW: usize = 300;
+
+/// Measurements of a single frame, times are in seconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameSample{
+    /// Number of the frame since the renderer started
+    pub frame: u64,
+    /// Time since the previous frame started
+    pub frame_time: f32,
+    /// Time spent in `Renderer::draw`, including serialization and submitting the draws
+    pub cpu_time: f32,
+    /// GPU time of the latest frame whose timer query finished, those come in a few frames late.
+    /// `None` without timer queries or when no query finished since the last frame.
+    pub gpu_time: Option<f32>,
+    /// Time spent serializing the scene, 0 when it didn't change
+    pub serialize_time: f32,
+    /// Rom words the scene was serialized to, 0 when the previous rom was kept
+    pub rom_words: u32,
+}
+
+/// A value recorded for every frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameMetric{
+    FrameTime,
+    CpuTime,
+    GpuTime,
+    SerializeTime,
+    RomWords,
+}
+
+impl FrameMetric{
+    pub const ALL: [FrameMetric;5] = [FrameMetric::FrameTime, FrameMetric::CpuTime, FrameMetric::GpuTime, FrameMetric::SerializeTime, FrameMetric::RomWords];
+
+    /// Column name in exported CSV.
+    pub fn name(&self) -> &'static str{
+        match self{
+            FrameMetric::FrameTime => "frame_time",
+            FrameMetric::CpuTime => "cpu_time",
+            FrameMetric::GpuTime => "gpu_time",
+            FrameMetric::SerializeTime => "serialize_time",
+            FrameMetric::RomWords => "rom_words",
+        }
+    }
+}
+
+impl FrameSample{
+    pub fn get(&self, metric: FrameMetric) -> Option<f32>{
+        match metric{
+            FrameMetric::FrameTime => Some(self.frame_time),
+            FrameMetric::CpuTime => Some(self.cpu_time),
+            FrameMetric::GpuTime => self.gpu_time,
+            FrameMetric::SerializeTime => Some(self.serialize_time),
+            FrameMetric::RomWords => Some(self.rom_words as f32),
+        }
+    }
+
+    fn write_csv(&self, writer: &mut dyn Write) -> io::Result<()>{
+        write!(writer, "{}", self.frame)?;
+        for metric in FrameMetric::ALL{
+            match self.get(metric){
+                Some(value) => write!(writer, ",{}", value)?,
+                None => write!(writer, ",")?,
+            }
+        }
+        writeln!(writer)
+    }
+}
+
+fn write_csv_header(writer: &mut dyn Write) -> io::Result<()>{
+    write!(writer, "frame")?;
+    for metric in FrameMetric::ALL{
+        write!(writer, ",{}", metric.name())?;
+    }
+    writeln!(writer)
+}
+
+/// Spread of a metric over the frames in the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary{
+    pub min: f32,
+    pub average: f32,
+    pub max: f32,
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+}
+
+/// Value below which `percent` of the sorted values fall, by nearest rank.
+fn nearest_rank(sorted: &[f32], percent: f32) -> f32{
+    let rank = (percent.clamp(0.0, 100.0) / 100.0 * sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// The last frames the renderer drew, readable from `App::frame_finished` and through `Renderer::frame_stats`.
+pub struct FrameStats{
+    samples: VecDeque<FrameSample>,
+    window: usize,
+    csv: Option<Box<dyn Write>>,
+}
+
+impl Default for FrameStats{
+    fn default() -> Self {
+        Self::new(DEFAULT_STATS_WINDOW)
+    }
+}
+
+impl FrameStats{
+    /// Keeps the last `window` frames.
+    pub fn new(window: usize) -> Self{
+        assert!(window > 0, "Frame stats need a window of at least one frame");
+        Self{
+            samples: VecDeque::with_capacity(window),
+            window,
+            csv: None,
+        }
+    }
+
+    pub fn window(&self) -> usize{
+        self.window
+    }
+
+    /// Adds a frame, dropping the oldest one once the window is full.
+    /// Fails when the frame couldn't be written to the CSV export, which is stopped then.
+    pub fn push(&mut self, sample: FrameSample) -> io::Result<()>{
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+
+        if let Some(writer) = self.csv.as_mut(){
+            if let Err(e) = sample.write_csv(writer) {
+                self.csv = None;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn latest(&self) -> Option<&FrameSample>{
+        self.samples.back()
+    }
+
+    /// Frames in the window, oldest first.
+    pub fn samples(&self) -> impl Iterator<Item = &FrameSample>{
+        self.samples.iter()
+    }
+
+    pub fn len(&self) -> usize{
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.samples.is_empty()
+    }
+
+    pub fn clear(&mut self){
+        self.samples.clear();
+    }
+
+    /// Values of a metric in the window, sorted, frames without a value are left out.
+    fn sorted(&self, metric: FrameMetric) -> Vec<f32>{
+        let mut values = self.samples.iter().filter_map(|x| x.get(metric)).collect::<Vec<_>>();
+        values.sort_by(f32::total_cmp);
+        values
+    }
+
+    /// `None` when no frame in the window has a value for the metric.
+    pub fn summary(&self, metric: FrameMetric) -> Option<Summary>{
+        let values = self.sorted(metric);
+        if values.is_empty() {
+            return None;
+        }
+        Some(Summary{
+            min: values[0],
+            average: values.iter().sum::<f32>() / values.len() as f32,
+            max: values[values.len() - 1],
+            p50: nearest_rank(&values, 50.0),
+            p95: nearest_rank(&values, 95.0),
+            p99: nearest_rank(&values, 99.0),
+        })
+    }
+
+    /// Value `percent` of the frames in the window stay at or below.
+    pub fn percentile(&self, metric: FrameMetric, percent: f32) -> Option<f32>{
+        let values = self.sorted(metric);
+        (!values.is_empty()).then(|| nearest_rank(&values, percent))
+    }
+
+    /// Frames per second over the window.
+    pub fn fps(&self) -> Option<f32>{
+        self.summary(FrameMetric::FrameTime).filter(|x| x.average > 0.0).map(|x| 1.0 / x.average)
+    }
+
+    /// Writes a header and every frame in the window as CSV, times in seconds.
+    pub fn write_csv(&self, writer: &mut dyn Write) -> io::Result<()>{
+        write_csv_header(writer)?;
+        for sample in &self.samples{
+            sample.write_csv(writer)?;
+        }
+        Ok(())
+    }
+
+    /// `Some` writes a header and then a CSV row for every frame pushed from now on, `None` stops the export.
+    pub fn set_csv_export(&mut self, writer: Option<Box<dyn Write>>) -> io::Result<()>{
+        if let Some(mut old) = self.csv.take(){
+            old.flush()?;
+        }
+        if let Some(mut writer) = writer{
+            write_csv_header(&mut writer)?;
+            self.csv = Some(writer);
+        }
+        Ok(())
+    }
+}
+
+/// Frames that may wait for their timer query before new frames stop being timed.
+const MAX_PENDING_QUERIES: usize = 4;
+
+/// Times the GPU work of frames with timer queries, results are only read once ready so the CPU never waits on them.
+pub(super) struct GpuTimer{
+    free: Vec<u32>,
+    pending: VecDeque<u32>,
+    active: Option<u32>,
+}
+
+impl GpuTimer{
+    /// `None` where timer queries are missing, WebGL only has them as an extension.
+    pub fn new() -> Option<Self>{
+        if cfg!(target_arch = "wasm32") {
+            return None;
+        }
+        Some(Self{
+            free: Vec::new(),
+            pending: VecDeque::new(),
+            active: None,
+        })
+    }
+
+    pub fn begin(&mut self){
+        if self.pending.len() >= MAX_PENDING_QUERIES {
+            return;
+        }
+        let query = self.free.pop().unwrap_or_else(|| {
+            let mut query = 0;
+            unsafe{ gl::glGenQueries(1, &mut query); }
+            query
+        });
+        unsafe{ gl::glBeginQuery(gl::GL_TIME_ELAPSED, query); }
+        self.active = Some(query);
+    }
+
+    pub fn end(&mut self){
+        if let Some(query) = self.active.take(){
+            unsafe{ gl::glEndQuery(gl::GL_TIME_ELAPSED); }
+            self.pending.push_back(query);
+        }
+    }
+
+    /// Seconds the latest frame finished since the last poll took.
+    pub fn poll(&mut self) -> Option<f32>{
+        let mut latest = None;
+        while let Some(&query) = self.pending.front(){
+            let mut available = 0;
+            unsafe{ gl::glGetQueryObjectiv(query, gl::GL_QUERY_RESULT_AVAILABLE, &mut available); }
+            if available == 0 {
+                break;
+            }
+            let mut nanoseconds = 0;
+            unsafe{ gl::glGetQueryObjectui64v(query, gl::GL_QUERY_RESULT, &mut nanoseconds); }
+            latest = Some(nanoseconds as f32 * 1e-9);
+            self.pending.pop_front();
+            self.free.push(query);
+        }
+        latest
+    }
+}
+
+impl Drop for GpuTimer{
+    fn drop(&mut self){
+        let queries = self.free.drain(..).chain(self.pending.drain(..)).chain(self.active.take()).collect::<Vec<_>>();
+        if !queries.is_empty() {
+            unsafe{ gl::glDeleteQueries(queries.len() as i32, queries.as_ptr()); }
+        }
+    }
+}
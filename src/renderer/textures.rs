@@ -0,0 +1,50 @@
+use miniquad::{Context, Texture, TextureWrap};
+
+/// Image behind a texture registered through `Renderer::register_texture`.
+pub enum TextureData{
+    /// Rows of rgba8 pixels, uploaded once the renderer has a context. Repeats outside of `[0, 1]`.
+    Rgba8{ width: u16, height: u16, pixels: Vec<u8> },
+    /// A texture created by the app, used as is.
+    Loaded(Texture),
+}
+
+impl TextureData{
+    pub fn create(&self, ctx: &mut Context) -> Texture{
+        match self{
+            TextureData::Rgba8{ width, height, pixels } => {
+                let texture = Texture::from_rgba8(ctx, *width, *height, pixels);
+                texture.set_wrap(ctx, TextureWrap::Repeat);
+                texture
+            },
+            TextureData::Loaded(texture) => *texture,
+        }
+    }
+}
+
+/// Sampler uniforms of the registered textures, and `vec4 sample_texture(int slot, vec2 uv)` selecting between them.
+/// GLSL 330 can't index samplers with values from the rom, so every slot gets its own case.
+pub fn textures_source(names: &[&str]) -> String{
+    let uniforms = names.iter()
+        .map(|name| format!("uniform sampler2D {};", name))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let cases = names.iter()
+        .enumerate()
+        .map(|(slot, name)| format!("case {}: return texture({}, uv);", slot, name))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("{}
+
+        // Normal of the surface being shaded, set before tex methods are called
+        vec3 hit_normal = vec3(0.0, 1.0, 0.0);
+
+        vec4 sample_texture(int slot, in vec2 uv){{
+            switch (slot){{
+                {}
+                default: return vec4(1.0, 0.0, 1.0, 1.0);
+            }}
+        }}
+
+        {}", uniforms, cases, include_str!("../../sdf/textures.glsl"))
+}
@@ -0,0 +1,191 @@
+//! Checks the shipped bound methods against their sdf methods, a bound may never cull a ray that hits.
+//! Sdf methods that aren't exact are checked to never overestimate the distance to their surface.
+
+use miniquad_raytrace::renderer::{HIT_DISTANCE, math::{Vec3, vec3}, cpu::primitives::*};
+
+struct Rng(u64);
+
+impl Rng{
+    fn next(&mut self) -> f32{
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn range(&mut self, min: f32, max: f32) -> f32{
+        min + (max - min) * self.next()
+    }
+
+    fn vec(&mut self, min: f32, max: f32) -> Vec3{
+        vec3(self.range(min, max), self.range(min, max), self.range(min, max))
+    }
+
+    fn direction(&mut self) -> Vec3{
+        loop{
+            let v = self.vec(-1.0, 1.0);
+            let len = v.length();
+            if len > 0.01 && len <= 1.0 {
+                return v / len;
+            }
+        }
+    }
+}
+
+const RAYS: usize = 2000;
+const STEP: f32 = 0.005;
+const LENGTH: f32 = 30.0;
+
+/// Walks rays through and around the shape and checks that every point closer than the
+/// hit distance lies inside the interval the bound returned for that ray.
+fn check_bound(name: &str, rng: &mut Rng, sdf: impl Fn(Vec3) -> f32, bound: impl Fn(Vec3, Vec3) -> Interval){
+    for _ in 0..RAYS{
+        let origin = rng.vec(-6.0, 6.0);
+        // Aim most rays at the shape so grazing hits get covered as well
+        let ray = if rng.next() < 0.75 {
+            (rng.vec(-1.5, 1.5) - origin).normalize()
+        }
+        else{
+            rng.direction()
+        };
+        let interval = bound(origin, ray);
+
+        let mut t = 0.0;
+        while t < LENGTH{
+            let d = sdf(origin + ray * t);
+            if d < HIT_DISTANCE {
+                assert!(
+                    interval.0 <= t && t <= interval.1,
+                    "{}: ray from {:?} along {:?} is within {} of the surface at t = {}, but the bound returned {:?}",
+                    name, origin, ray, d, t, interval
+                );
+            }
+            // Far from the surface the sdf tells how much can be skipped safely
+            t += (d.abs() - HIT_DISTANCE).max(STEP);
+        }
+    }
+}
+
+#[test]
+fn sphere_bound_covers_sdf(){
+    let mut rng = Rng(0x1234_5678);
+    for _ in 0..10{
+        let center = rng.vec(-1.0, 1.0);
+        let radius = rng.range(0.1, 2.0);
+        check_bound("sphere", &mut rng, |p| sdf_sphere(p, center, radius), |o, r| bound_sphere(o, r, center, radius));
+    }
+}
+
+#[test]
+fn plane_bound_covers_sdf(){
+    let mut rng = Rng(0x2345_6789);
+    for _ in 0..20{
+        let normal = rng.direction();
+        let height = rng.range(-3.0, 3.0);
+        check_bound("plane", &mut rng, |p| sdf_plane(p, normal, height), |o, r| bound_plane(o, r, normal, height));
+    }
+}
+
+#[test]
+fn plane_bound_handles_tilted_normals(){
+    let normal = vec3(1.0, 1.0, 0.0).normalize();
+    // A ray straight down the normal from above hits at the expected distance
+    let (tmin, tmax) = bound_plane(normal * 5.0, -normal, normal, 2.0);
+    assert!(tmin <= 3.0 && 3.0 <= tmax, "{:?}", (tmin, tmax));
+    // Rays moving away from the plane miss it
+    assert!(!interval_hit(bound_plane(normal * 5.0, normal, normal, 2.0)));
+    // Starting behind the surface is always a hit
+    assert!(interval_hit(bound_plane(Vec3::splat(0.0), normal, normal, 2.0)));
+}
+
+#[test]
+fn plane_bound_keeps_far_hits(){
+    let normal = vec3(0.0, 1.0, 0.0);
+    let ray = vec3(1.0, -0.0005, 0.0).normalize();
+    let (tmin, tmax) = bound_plane(vec3(0.0, 1.0, 0.0), ray, normal, 0.0);
+    assert!(tmin > 1000.0 && tmin <= tmax, "{:?}", (tmin, tmax));
+}
+
+#[test]
+fn box_bounds_cover_sdf(){
+    let mut rng = Rng(0x3456_789a);
+    for _ in 0..10{
+        let center = rng.vec(-1.0, 1.0);
+        let half_size = rng.vec(0.05, 1.5);
+        let rounding = rng.range(0.0, half_size.min_element());
+        check_bound("box", &mut rng, |p| sdf_box(p, center, half_size), |o, r| bound_box(o, r, center, half_size));
+        check_bound("rounded box", &mut rng, |p| sdf_rounded_box(p, center, half_size, rounding), |o, r| bound_rounded_box(o, r, center, half_size, rounding));
+    }
+}
+
+#[test]
+fn torus_bound_covers_sdf(){
+    let mut rng = Rng(0x4567_89ab);
+    for _ in 0..10{
+        let center = rng.vec(-1.0, 1.0);
+        let major = rng.range(0.2, 2.0);
+        let minor = rng.range(0.05, major);
+        check_bound("torus", &mut rng, |p| sdf_torus(p, center, major, minor), |o, r| bound_torus(o, r, center, major, minor));
+    }
+}
+
+#[test]
+fn round_bounds_cover_sdf(){
+    let mut rng = Rng(0x5678_9abc);
+    for _ in 0..10{
+        let center = rng.vec(-1.0, 1.0);
+        let half_height = rng.range(0.05, 2.0);
+        let radius = rng.range(0.05, 1.5);
+        check_bound("capsule", &mut rng, |p| sdf_capsule(p, center, half_height, radius), |o, r| bound_capsule(o, r, center, half_height, radius));
+        check_bound("cylinder", &mut rng, |p| sdf_cylinder(p, center, half_height, radius), |o, r| bound_cylinder(o, r, center, half_height, radius));
+        check_bound("cone", &mut rng, |p| sdf_cone(p, center, half_height, radius), |o, r| bound_cone(o, r, center, half_height, radius));
+        check_bound("hex prism", &mut rng, |p| sdf_hex_prism(p, center, radius, half_height), |o, r| bound_hex_prism(o, r, center, radius, half_height));
+    }
+}
+
+#[test]
+fn ellipsoid_bound_covers_sdf(){
+    let mut rng = Rng(0x6789_abcd);
+    for _ in 0..10{
+        let center = rng.vec(-1.0, 1.0);
+        let radii = rng.vec(0.1, 2.0);
+        check_bound("ellipsoid", &mut rng, |p| sdf_ellipsoid(p, center, radii), |o, r| bound_ellipsoid(o, r, center, radii));
+    }
+}
+
+/// The true distance is at most the distance to any point on the surface, a sdf above it can march past the shape.
+#[test]
+fn ellipsoid_sdf_never_overestimates(){
+    let mut rng = Rng(0x6789_abce);
+    for radii in [vec3(1.0, 1.0, 1.0), vec3(0.1, 2.0, 1.0), vec3(3.0, 0.05, 0.5), rng.vec(0.1, 2.0)]{
+        let center = rng.vec(-1.0, 1.0);
+        let surface = (0..4000).map(|_| center + rng.direction() * radii).collect::<Vec<_>>();
+        for _ in 0..500{
+            let p = center + rng.vec(-4.0, 4.0);
+            let nearest = surface.iter().map(|x| x.distance(p)).fold(f32::MAX, f32::min);
+            let d = sdf_ellipsoid(p, center, radii);
+            assert!(d.abs() <= nearest + 1e-4, "ellipsoid {:?}: sdf {} at {:?}, but a surface point is {} away", radii, d, p, nearest);
+        }
+    }
+}
+
+#[test]
+fn octahedron_bound_covers_sdf(){
+    let mut rng = Rng(0x789a_bcde);
+    for _ in 0..10{
+        let center = rng.vec(-1.0, 1.0);
+        let size = rng.range(0.1, 2.0);
+        check_bound("octahedron", &mut rng, |p| sdf_octahedron(p, center, size), |o, r| bound_octahedron(o, r, center, size));
+    }
+}
+
+#[test]
+fn segment_bound_covers_sdf(){
+    let mut rng = Rng(0x89ab_cdef);
+    for _ in 0..10{
+        let start = rng.vec(-1.5, 1.5);
+        let end = rng.vec(-1.5, 1.5);
+        let radius = rng.range(0.02, 0.5);
+        check_bound("segment", &mut rng, |p| sdf_segment(p, start, end, radius), |o, r| bound_segment(o, r, start, end, radius));
+    }
+}
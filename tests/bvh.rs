@@ -0,0 +1,148 @@
+//! Checks the bvh records written by `SimpleScene`, the shader trusts their boxes, skips and counts blindly.
+
+use miniquad_raytrace::renderer::{bvh::{Aabb, BVH_NODE_LEN}, scene::{Serializeable, SceneSerializer, SimpleScene, SceneNode, CsgOp, Transform, OP_BVH_NODE}};
+
+/// Record of two words, its id and index, with the box given by the test.
+struct Marker{
+    index: u32,
+    aabb: Option<Aabb>
+}
+
+impl Serializeable for Marker{
+    fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
+        serializer.write_value(1);
+        serializer.write_value(self.index);
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        self.aabb
+    }
+}
+
+fn marker_box(i: u32) -> Aabb{
+    let x = (i * 7 % 13) as f32;
+    let y = (i * 5 % 11) as f32;
+    Aabb::from_center([x, y, (i % 3) as f32], [0.5, 0.25 + (i % 4) as f32 * 0.1, 0.5])
+}
+
+/// Walks the records from `start` to `end`, checking that every marker lies inside `enclosing`,
+/// and returns the indices of the markers found.
+fn walk(rom: &[u32], mut pnt: usize, end: usize, enclosing: &[Aabb], boxes: &[Option<Aabb>]) -> Vec<u32>{
+    let mut found = Vec::new();
+    while pnt < end{
+        if rom[pnt] == OP_BVH_NODE as u32 {
+            let skip = rom[pnt + 1] as usize;
+            let count = rom[pnt + 2] as usize;
+            let aabb = Aabb::new(
+                [0, 1, 2].map(|x| f32::from_bits(rom[pnt + 3 + x])),
+                [0, 1, 2].map(|x| f32::from_bits(rom[pnt + 6 + x])));
+            for outer in enclosing{
+                assert!(outer.contains(&aabb), "node {:?} sticks out of {:?}", aabb, outer);
+            }
+
+            let start = pnt + BVH_NODE_LEN;
+            let inner = walk(rom, start, start + skip, &[enclosing, &[aabb]].concat(), boxes);
+            assert!(inner.len() >= 2, "nodes should hold at least two instances");
+            assert_eq!(inner.len(), count, "node at {} counts the wrong number of instances", pnt);
+            found.extend(inner);
+            pnt = start + skip;
+        }
+        else{
+            assert_eq!(rom[pnt], 1, "skip at {} doesn't land on a record", pnt);
+            let index = rom[pnt + 1];
+            if let Some(aabb) = boxes[index as usize]{
+                for outer in enclosing{
+                    assert!(outer.contains(&aabb), "instance {} sticks out of {:?}", index, outer);
+                }
+            }
+            else{
+                assert!(enclosing.is_empty(), "unbounded instance {} was put into the bvh", index);
+            }
+            found.push(index);
+            pnt += 2;
+        }
+    }
+    assert_eq!(pnt, end, "records overrun their node");
+    found
+}
+
+fn serialize(scene: &SimpleScene) -> (Vec<u32>, usize){
+    let mut rom = vec![0; 1024];
+    let mut serializer = SceneSerializer::new(&mut rom);
+    scene.serialize(&mut serializer);
+    let len = serializer.position();
+    (rom, len)
+}
+
+#[test]
+fn bvh_covers_every_instance_once(){
+    let boxes = (0..40).map(|i| if i % 9 == 0 { None } else { Some(marker_box(i)) }).collect::<Vec<_>>();
+    let mut scene = SimpleScene::new();
+    for (index, aabb) in boxes.iter().enumerate(){
+        scene.add_instance(Marker{ index: index as u32, aabb: *aabb });
+    }
+
+    let (rom, len) = serialize(&scene);
+    let mut found = walk(&rom, 0, len, &[], &boxes);
+    found.sort();
+    assert_eq!(found, (0..40).collect::<Vec<_>>());
+}
+
+/// Nodes whose skip was dropped by the full rom can't be patched, the overflow is reported instead.
+#[test]
+fn overflowing_bvh_is_reported(){
+    let mut scene = SimpleScene::new();
+    for i in 0..40{
+        scene.add_instance(Marker{ index: i, aabb: Some(marker_box(i)) });
+    }
+    for len in [8, 20, 100]{
+        let mut rom = vec![0; len];
+        let mut serializer = SceneSerializer::new(&mut rom);
+        scene.serialize(&mut serializer);
+        assert!(serializer.overflowed());
+    }
+}
+
+#[test]
+#[should_panic(expected = "never written")]
+fn patching_unwritten_values_panics(){
+    let mut rom = vec![0; 8];
+    let mut serializer = SceneSerializer::new(&mut rom);
+    serializer.write_value(1);
+    serializer.patch(1, 2);
+}
+
+#[test]
+fn single_instance_gets_no_node(){
+    let mut scene = SimpleScene::new();
+    scene.add_instance(Marker{ index: 0, aabb: Some(marker_box(0)) });
+    let (rom, len) = serialize(&scene);
+    assert_eq!(&rom[..len], &[1, 0]);
+}
+
+#[test]
+fn node_boxes_follow_transforms_and_groups(){
+    let marker = |index: u32| SceneNode::instance(Marker{ index, aabb: Some(Aabb::from_radius([0.0;3], 1.0)) });
+    let moved = marker(0).transformed(Transform::translate([10.0, 0.0, 0.0]).with_scale([2.0;3]));
+    assert_eq!(moved.aabb(), Some(Aabb::new([8.0, -2.0, -2.0], [12.0, 2.0, 2.0])));
+
+    let rotated = marker(0).transformed(Transform::rotate([0.0, 0.0, 1.0], std::f32::consts::FRAC_PI_4));
+    let aabb = rotated.aabb().unwrap();
+    assert!((aabb.max[0] - 2.0f32.sqrt()).abs() < 1e-5, "{:?}", aabb);
+    let unrotated = marker(0).transformed(Transform::rotate([0.0;3], 1.0));
+    assert_eq!(unrotated.aabb(), Some(Aabb::from_radius([0.0;3], 1.0)));
+
+    let blended = SceneNode::group(CsgOp::SmoothUnion(0.5), vec![marker(0), marker(1).transformed(Transform::translate([3.0, 0.0, 0.0]))]);
+    assert_eq!(blended.aabb(), Some(Aabb::new([-1.5, -1.5, -1.5], [4.5, 1.5, 1.5])));
+    assert_eq!(blended.instance_count(), 2);
+
+    let unbounded = SceneNode::union(vec![marker(0), SceneNode::instance(Marker{ index: 1, aabb: None })]);
+    assert_eq!(unbounded.aabb(), None);
+    assert_eq!(SceneNode::subtract(marker(0), vec![SceneNode::instance(Marker{ index: 1, aabb: None })]).aabb(), Some(Aabb::from_radius([0.0;3], 1.0)));
+}
+
+#[test]
+#[should_panic(expected = "have to be positive")]
+fn zero_scale_panics(){
+    Transform::translate([1.0, 0.0, 0.0]).with_scale([1.0, 0.0, 1.0]);
+}
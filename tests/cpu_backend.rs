@@ -0,0 +1,165 @@
+//! Renders small scenes with the CPU backend, which follows the scene shader without a GL context.
+
+use miniquad_raytrace::renderer::{
+    bvh::Aabb,
+    cpu::{backend::CpuBackend, methods::CpuMethods},
+    primitives::{Primitive, PrimitiveIds},
+    scene::{Serializeable, SceneSerializer, SimpleScene, SceneNode, CsgOp, Material, Animation},
+};
+
+fn ellipsoid_backend() -> CpuBackend{
+    let mut methods = CpuMethods::new();
+    let library = methods.register_primitive_library();
+    let mut scene = SimpleScene::new();
+    let ellipsoid = library.instance(Primitive::Ellipsoid, &[0.0, 0.0, 5.0, 1.0, 1.0, 1.0], [1.0, 0.0, 0.0]);
+    scene.add_instance(SceneNode::instance(ellipsoid));
+
+    let mut backend = CpuBackend::new(methods);
+    backend.load_scene(&scene).unwrap();
+    backend
+}
+
+#[test]
+fn ellipsoid_covers_center(){
+    let backend = ellipsoid_backend();
+    let image = backend.render(64, 48);
+    assert_eq!(image.pixels.len(), 64 * 48 * 4);
+
+    let center = image.pixel(32, 24);
+    assert!(center[0] > 0 && center[1] == 0 && center[2] == 0, "center {:?} should be the red ellipsoid", center);
+    let corner = image.pixel(0, 0);
+    assert_ne!(corner, center, "corner should show the background");
+}
+
+#[test]
+fn thread_count_does_not_change_image(){
+    let mut backend = ellipsoid_backend();
+    backend.set_threads(1);
+    let single = backend.render(70, 40);
+    backend.set_threads(7);
+    assert_eq!(single, backend.render(70, 40));
+}
+
+#[test]
+fn subtraction_carves_base(){
+    let mut methods = CpuMethods::new();
+    let library = methods.register_primitive_library();
+    let base = library.instance(Primitive::Ellipsoid, &[0.0, 0.0, 5.0, 1.0, 1.0, 1.0], [1.0, 0.0, 0.0]);
+    let cutter = library.instance(Primitive::Ellipsoid, &[0.0, 0.0, 3.5, 1.0, 1.0, 1.0], [0.0, 1.0, 0.0]);
+    let mut scene = SimpleScene::new();
+    scene.add_instance(SceneNode::group(CsgOp::Subtract, vec![SceneNode::instance(base), SceneNode::instance(cutter)]));
+
+    let mut backend = CpuBackend::new(methods);
+    backend.load_scene(&scene).unwrap();
+    let solid = ellipsoid_backend().render(32, 32);
+    let image = backend.render(32, 32);
+
+    // The carved surface keeps the material of the base but faces the camera at a different angle
+    let center = image.pixel(16, 16);
+    assert!(center[0] > 0 && center[1] == 0, "center {:?} should keep the base color", center);
+    assert_ne!(center, solid.pixel(16, 16));
+}
+
+#[test]
+fn oversized_scene_is_rejected(){
+    let mut methods = CpuMethods::new();
+    let library = methods.register_primitive_library();
+    let mut scene = SimpleScene::new();
+    for i in 0..1000{
+        let ellipsoid = library.instance(Primitive::Ellipsoid, &[i as f32, 0.0, 5.0, 0.1, 0.1, 0.1], [1.0, 0.0, 0.0]);
+        scene.add_instance(SceneNode::instance(ellipsoid));
+    }
+
+    let mut backend = CpuBackend::new(methods);
+    assert!(backend.load_scene(&scene).is_err());
+}
+
+/// Spins are normalized by the length of their axis, a zero axis has to leave the instance in place.
+#[test]
+fn zero_spin_axis_does_not_rotate(){
+    let render = |animation: Option<Animation>|{
+        let mut methods = CpuMethods::new();
+        let library = methods.register_primitive_library();
+        let ellipsoid = library.instance(Primitive::Ellipsoid, &[0.3, 0.0, 5.0, 1.2, 0.5, 0.8], [1.0, 0.0, 0.0]);
+        let node = SceneNode::instance(ellipsoid);
+        let mut scene = SimpleScene::new();
+        scene.add_instance(match animation{
+            Some(animation) => node.animated(animation),
+            None => node,
+        });
+
+        let mut backend = CpuBackend::new(methods);
+        backend.load_scene(&scene).unwrap();
+        backend.set_elapsed(1.5);
+        backend.render(32, 32)
+    };
+    assert_eq!(render(Some(Animation::Spin{ axis: [0.0;3], speed: 2.0 })), render(None));
+}
+
+/// The blend divides by the radius, a zero radius has to give the hard union instead of NaN.
+#[test]
+fn zero_blend_radius_is_a_hard_union(){
+    let render = |op: CsgOp|{
+        let mut methods = CpuMethods::new();
+        let library = methods.register_primitive_library();
+        let left = library.instance(Primitive::Ellipsoid, &[-0.6, 0.0, 5.0, 1.0, 1.0, 1.0], [1.0, 0.0, 0.0]);
+        let right = library.instance(Primitive::Ellipsoid, &[0.6, 0.0, 5.0, 1.0, 1.0, 1.0], [0.0, 1.0, 0.0]);
+        let mut scene = SimpleScene::new();
+        scene.add_instance(SceneNode::group(op, vec![SceneNode::instance(left), SceneNode::instance(right)]));
+
+        let mut backend = CpuBackend::new(methods);
+        backend.load_scene(&scene).unwrap();
+        backend.render(32, 32)
+    };
+    assert_eq!(render(CsgOp::SmoothUnion(0.0)), render(CsgOp::Union));
+}
+
+/// Sphere with a whole material, written into the bvh only when it has a box.
+struct MaterialSphere{
+    ids: PrimitiveIds,
+    material_id: u32,
+    center: [f32;3],
+    radius: f32,
+    material: Material,
+    in_bvh: bool
+}
+
+impl Serializeable for MaterialSphere{
+    fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
+        serializer.write_value(self.ids.bound);
+        self.center.serialize(serializer);
+        self.radius.serialize(serializer);
+        serializer.write_value(self.ids.sdf);
+        self.center.serialize(serializer);
+        self.radius.serialize(serializer);
+        serializer.write_value(11);
+        serializer.write_value(self.material_id);
+        self.material.serialize(serializer);
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        self.in_bvh.then(|| Aabb::from_radius(self.center, self.radius))
+    }
+}
+
+/// Refracted rays march inside the first glass sphere with a negative union, the node holding the
+/// overlapping second one still has to be entered or the ray leaves the first one while inside the second.
+#[test]
+fn bvh_nodes_are_entered_inside_solids(){
+    let render = |in_bvh: bool|{
+        let mut methods = CpuMethods::new();
+        let sphere = methods.register_sphere();
+        let material_id = methods.register_material();
+        let glass = |center: [f32;3], radius: f32| MaterialSphere{ ids: sphere, material_id, center, radius, material: Material::glass([0.9, 1.0, 0.9], 1.5), in_bvh };
+
+        let mut scene = SimpleScene::new();
+        scene.add_instance(glass([-0.5, 0.0, 5.0], 1.0));
+        scene.add_instance(glass([0.5, 0.0, 5.0], 1.0));
+        scene.add_instance(MaterialSphere{ material: Material::diffuse([1.0, 0.3, 0.1]), ..glass([4.0, 0.0, 5.0], 0.5) });
+
+        let mut backend = CpuBackend::new(methods);
+        backend.load_scene(&scene).unwrap();
+        backend.render(48, 32)
+    };
+    assert_eq!(render(true), render(false));
+}
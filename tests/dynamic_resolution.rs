@@ -0,0 +1,54 @@
+//! Checks how `ScaleController` reacts to frame times, it should follow sustained load without flickering.
+
+use miniquad_raytrace::renderer::algorithms::{DynamicResolution, ScaleController};
+
+const TARGET: f32 = 0.016;
+
+fn controller() -> ScaleController{
+    ScaleController::new(DynamicResolution{
+        target_frame_time: TARGET,
+        ..Default::default()
+    })
+}
+
+/// Feeds the same frame time `frames` times, returning the scale after each frame.
+fn run(controller: &mut ScaleController, mut scale: f32, frame_time: f32, frames: usize) -> Vec<f32>{
+    (0..frames).map(|_| {
+        scale = controller.update(frame_time, scale);
+        scale
+    }).collect()
+}
+
+#[test]
+fn slow_frames_coarsen_the_scale(){
+    let mut controller = controller();
+    let scales = run(&mut controller, 1.0, TARGET * 2.0, 100);
+    assert!(scales.windows(2).all(|x| x[1] >= x[0]), "{:?}", scales);
+    assert_eq!(*scales.last().unwrap(), 4.0);
+}
+
+#[test]
+fn fast_frames_refine_the_scale(){
+    let mut controller = controller();
+    let scales = run(&mut controller, 4.0, TARGET * 0.5, 100);
+    assert!(scales.windows(2).all(|x| x[1] <= x[0]), "{:?}", scales);
+    assert_eq!(*scales.last().unwrap(), 1.0);
+}
+
+#[test]
+fn frames_within_the_headroom_keep_the_scale(){
+    let mut controller = controller();
+    let scales = run(&mut controller, 2.0, TARGET * 0.9, 100);
+    assert!(scales.iter().all(|x| *x == 2.0), "{:?}", scales);
+}
+
+#[test]
+fn single_spikes_are_ignored(){
+    let mut controller = controller();
+    let mut scale = 2.0;
+    for frame in 0..200{
+        let frame_time = if frame % 20 == 0 { TARGET * 3.0 } else { TARGET * 0.9 };
+        scale = controller.update(frame_time, scale);
+        assert_eq!(scale, 2.0, "changed scale on frame {}", frame);
+    }
+}
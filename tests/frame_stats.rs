@@ -0,0 +1,87 @@
+//! Checks the rolling window of `FrameStats`, its summaries and the CSV it writes.
+
+use std::{io::Write, rc::Rc, cell::RefCell};
+
+use miniquad_raytrace::renderer::stats::{FrameStats, FrameSample, FrameMetric};
+
+fn sample(frame: u64, cpu_time: f32) -> FrameSample{
+    FrameSample{
+        frame,
+        frame_time: 0.02,
+        cpu_time,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn window_keeps_the_latest_frames(){
+    let mut stats = FrameStats::new(3);
+    for frame in 0..5{
+        stats.push(sample(frame, frame as f32)).unwrap();
+    }
+    assert_eq!(stats.len(), 3);
+    assert_eq!(stats.samples().map(|x| x.frame).collect::<Vec<_>>(), vec![2, 3, 4]);
+    assert_eq!(stats.latest().unwrap().frame, 4);
+}
+
+#[test]
+fn summary_over_the_window(){
+    let mut stats = FrameStats::new(100);
+    for frame in 0..100{
+        stats.push(sample(frame, (100 - frame) as f32)).unwrap();
+    }
+    let summary = stats.summary(FrameMetric::CpuTime).unwrap();
+    assert_eq!((summary.min, summary.max), (1.0, 100.0));
+    assert_eq!(summary.average, 50.5);
+    assert_eq!((summary.p50, summary.p95, summary.p99), (50.0, 95.0, 99.0));
+    assert_eq!(stats.percentile(FrameMetric::CpuTime, 0.0), Some(1.0));
+    assert_eq!(stats.percentile(FrameMetric::CpuTime, 100.0), Some(100.0));
+    assert!((stats.fps().unwrap() - 50.0).abs() < 1e-3);
+}
+
+/// Frames whose timer query hasn't finished have no GPU time, they don't count as zero.
+#[test]
+fn missing_gpu_times_are_skipped(){
+    let mut stats = FrameStats::new(10);
+    assert_eq!(stats.summary(FrameMetric::GpuTime), None);
+    stats.push(sample(0, 1.0)).unwrap();
+    stats.push(FrameSample{ gpu_time: Some(0.004), ..sample(1, 1.0) }).unwrap();
+    let summary = stats.summary(FrameMetric::GpuTime).unwrap();
+    assert_eq!((summary.min, summary.average, summary.max), (0.004, 0.004, 0.004));
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn csv_export(){
+    let buffer = SharedBuffer::default();
+    let mut stats = FrameStats::new(1);
+    stats.push(sample(0, 0.5)).unwrap();
+    stats.set_csv_export(Some(Box::new(buffer.clone()))).unwrap();
+    stats.push(FrameSample{ gpu_time: Some(0.25), serialize_time: 0.125, rom_words: 42, ..sample(1, 0.5) }).unwrap();
+    stats.push(sample(2, 0.75)).unwrap();
+    stats.set_csv_export(None).unwrap();
+    stats.push(sample(3, 1.0)).unwrap();
+
+    assert_eq!(String::from_utf8(buffer.0.borrow().clone()).unwrap(),
+        "frame,frame_time,cpu_time,gpu_time,serialize_time,rom_words\n\
+        1,0.02,0.5,0.25,0.125,42\n\
+        2,0.02,0.75,,0,0\n");
+
+    let mut window = Vec::new();
+    stats.write_csv(&mut window).unwrap();
+    assert_eq!(String::from_utf8(window).unwrap(),
+        "frame,frame_time,cpu_time,gpu_time,serialize_time,rom_words\n\
+        3,0.02,1,,0,0\n");
+}
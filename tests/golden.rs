@@ -0,0 +1,302 @@
+//! Renders reference scenes with the CPU backend and compares them to the PNGs in `tests/golden`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write the current renders as the new references.
+//! Scenes that don't match leave `<name>.actual.png` and `<name>.diff.png` in the test's target directory.
+//!
+//! The references are written by the same `CpuBackend` they are compared against, so they only catch changes
+//! to its behaviour, they don't show the shader is right. The CPU methods are hand ports of the GLSL in `sdf/`,
+//! the only tie to the shipped shader is that every ported primitive is checked to read the parameters
+//! its GLSL methods declare, in the same order. Changing a GLSL method body means changing its port too.
+
+use std::{fs, path::PathBuf};
+
+use miniquad_raytrace::renderer::{
+    bvh::Aabb,
+    cpu::{backend::{CpuBackend, CpuImage}, methods::CpuMethods},
+    methods::DataDeserializer,
+    primitives::{Primitive, PrimitiveIds},
+    scene::{Serializeable, SceneSerializer, SimpleScene, SceneNode, CsgOp, Transform, DomainModifier, Animation, Material},
+};
+
+const WIDTH: u32 = 96;
+const HEIGHT: u32 = 72;
+/// Largest difference of a color channel that still counts as a match, renders differ slightly between platforms.
+const TOLERANCE: u8 = 3;
+
+/// An instance of any registered method set, optionally written without its bound.
+struct Instance{
+    ids: PrimitiveIds,
+    params: Vec<f32>,
+    tex: Vec<f32>,
+    bounded: bool,
+    aabb: Option<Aabb>
+}
+
+impl Instance{
+    fn sphere(ids: PrimitiveIds, center: [f32;3], radius: f32, color: [f32;3]) -> Self{
+        Self{
+            ids,
+            params: vec![center[0], center[1], center[2], radius],
+            tex: color.to_vec(),
+            bounded: true,
+            aabb: Some(Aabb::from_radius(center, radius))
+        }
+    }
+
+    fn plane(ids: PrimitiveIds, normal: [f32;3], height: f32) -> Self{
+        Self{
+            ids,
+            params: vec![normal[0], normal[1], normal[2], height],
+            tex: Vec::new(),
+            bounded: true,
+            aabb: None
+        }
+    }
+
+    fn primitive(ids: PrimitiveIds, primitive: Primitive, params: &[f32], color: [f32;3]) -> Self{
+        Self{
+            ids,
+            params: params.to_vec(),
+            tex: color.to_vec(),
+            bounded: true,
+            aabb: Some(primitive.aabb(params))
+        }
+    }
+
+    fn with_material(mut self, tex: u32, material: Material) -> Self{
+        self.ids.tex = tex;
+        let mut rom = vec![0; 10];
+        material.serialize(&mut SceneSerializer::new(&mut rom));
+        self.tex = rom.into_iter().map(f32::from_bits).collect();
+        self
+    }
+
+    fn unbounded(mut self) -> Self{
+        self.bounded = false;
+        self.aabb = None;
+        self
+    }
+}
+
+impl Serializeable for Instance{
+    fn serialize<'a>(&self, serializer: &mut SceneSerializer<'a>) {
+        if self.bounded {
+            serializer.write_value(self.ids.bound);
+            self.params[..].serialize(serializer);
+        }
+        else{
+            serializer.write_value(0);
+        }
+        serializer.write_value(self.ids.sdf);
+        self.params[..].serialize(serializer);
+        serializer.write_value(1 + self.tex.len() as u32);
+        serializer.write_value(self.ids.tex);
+        self.tex[..].serialize(serializer);
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        self.aabb
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf{
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name))
+}
+
+/// Marks every channel difference above the tolerance in red, matching pixels show the reference dimmed.
+fn diff_image(reference: &CpuImage, actual: &CpuImage) -> (CpuImage, usize, u8){
+    let mut mismatched = 0;
+    let mut largest = 0;
+    let pixels = reference.pixels.chunks(4).zip(actual.pixels.chunks(4)).flat_map(|(a, b)|{
+        let difference = a.iter().zip(b).map(|(a, b)| a.abs_diff(*b)).max().unwrap();
+        largest = largest.max(difference);
+        if difference > TOLERANCE {
+            mismatched += 1;
+            [255, 0, 0, 255]
+        }
+        else{
+            let gray = ((a[0] as u16 + a[1] as u16 + a[2] as u16) / 12) as u8;
+            [gray, gray, gray, 255]
+        }
+    }).collect();
+
+    (CpuImage{ width: reference.width, height: reference.height, pixels }, mismatched, largest)
+}
+
+/// Fails when more than `allowed` pixels are off by more than the tolerance.
+fn compare(name: &str, reference: &CpuImage, actual: &CpuImage, allowed: usize){
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let actual_path = out_dir.join(format!("{}.actual.png", name));
+    fs::create_dir_all(&out_dir).unwrap();
+
+    if (reference.width, reference.height) != (actual.width, actual.height) {
+        actual.save_png(&actual_path).unwrap();
+        panic!("{} is {}x{} but the reference is {}x{}, render written to {}",
+            name, actual.width, actual.height, reference.width, reference.height, actual_path.display());
+    }
+
+    let (diff, mismatched, largest) = diff_image(reference, actual);
+    if mismatched > allowed {
+        let diff_path = out_dir.join(format!("{}.diff.png", name));
+        actual.save_png(&actual_path).unwrap();
+        diff.save_png(&diff_path).unwrap();
+        panic!("{} has {} pixels off by up to {}, render written to {} and differences to {}",
+            name, mismatched, largest, actual_path.display(), diff_path.display());
+    }
+}
+
+fn check_golden(name: &str, backend: &CpuBackend){
+    let actual = backend.render(WIDTH, HEIGHT);
+    let path = golden_path(name);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        actual.save_png(&path).unwrap();
+        return;
+    }
+
+    let reference = fs::read(&path)
+        .unwrap_or_else(|e| panic!("Can't read {} ({}), run with UPDATE_GOLDEN=1 to create it", path.display(), e));
+    let reference = CpuImage::from_png(&reference).unwrap_or_else(|e| panic!("Can't decode {}: {}", path.display(), e));
+    compare(name, &reference, &actual, 0);
+}
+
+fn backend(methods: CpuMethods, scene: SimpleScene) -> CpuBackend{
+    let mut backend = CpuBackend::new(methods);
+    backend.load_scene(&scene).unwrap();
+    backend
+}
+
+#[test]
+fn sphere(){
+    let mut methods = CpuMethods::new();
+    let sphere = methods.register_sphere();
+    let mut scene = SimpleScene::new();
+    scene.add_instance(Instance::sphere(sphere, [0.0, 0.0, 5.0], 1.0, [1.0, 0.3, 0.1]));
+    scene.add_instance(Instance::sphere(sphere, [1.5, 0.8, 7.0], 0.7, [0.2, 0.4, 1.0]));
+
+    check_golden("sphere", &backend(methods, scene));
+}
+
+#[test]
+fn plane(){
+    let mut methods = CpuMethods::new();
+    let sphere = methods.register_sphere();
+    let plane = methods.register_plane();
+    let material = methods.register_material();
+    let mut scene = SimpleScene::new();
+    scene.add_instance(Instance::plane(plane, [0.0, 1.0, 0.0], -1.0));
+    scene.add_instance(Instance::sphere(sphere, [0.0, 0.0, 6.0], 1.0, [0.0; 3]).with_material(material, Material::mirror([0.9, 0.9, 0.9])));
+
+    let mut backend = backend(methods, scene);
+    backend.set_fog_density(0.03);
+    check_golden("plane", &backend);
+}
+
+fn primitive_grid(bounded: bool) -> CpuBackend{
+    let mut methods = CpuMethods::new();
+    let library = methods.register_primitive_library();
+    let mut scene = SimpleScene::new();
+    let params = |primitive: Primitive, center: [f32;3]|{
+        let [x, y, z] = center;
+        match primitive{
+            Primitive::Box => vec![x, y, z, 0.4, 0.3, 0.4],
+            Primitive::RoundedBox => vec![x, y, z, 0.4, 0.4, 0.4, 0.1],
+            Primitive::Torus => vec![x, y, z, 0.35, 0.12],
+            Primitive::Capsule | Primitive::Cylinder | Primitive::Cone => vec![x, y, z, 0.35, 0.25],
+            Primitive::Ellipsoid => vec![x, y, z, 0.45, 0.3, 0.3],
+            Primitive::Octahedron => vec![x, y, z, 0.45],
+            Primitive::HexPrism => vec![x, y, z, 0.35, 0.3],
+            Primitive::LineSegment => vec![x - 0.3, y - 0.3, z, x + 0.3, y + 0.3, z, 0.12],
+        }
+    };
+    for (i, primitive) in Primitive::ALL.iter().enumerate(){
+        let center = [(i % 5) as f32 - 2.0, if i < 5 { 0.6 } else { -0.6 }, 6.0];
+        let color = [0.3 + 0.07 * i as f32, 0.9 - 0.08 * i as f32, 0.5];
+        let instance = Instance::primitive(library.ids(*primitive), *primitive, &params(*primitive, center), color);
+        scene.add_instance(if bounded { instance } else { instance.unbounded() });
+    }
+    backend(methods, scene)
+}
+
+#[test]
+fn bounds(){
+    check_golden("bounds", &primitive_grid(true));
+}
+
+/// Bounds only skip work, the scene has to look the same when every instance is marched.
+/// Rays grazing a silhouette can end on either side of the hit distance, so a few of those may differ.
+#[test]
+fn bounds_match_unbounded(){
+    let bounded = primitive_grid(true).render(WIDTH, HEIGHT);
+    let unbounded = primitive_grid(false).render(WIDTH, HEIGHT);
+    compare("bounds_unbounded", &bounded, &unbounded, (WIDTH * HEIGHT / 200) as usize);
+}
+
+/// Nested groups, transforms, domain modifiers and animations all have to be framed right for the marcher to find the instances after them.
+#[test]
+fn framing(){
+    let mut methods = CpuMethods::new();
+    let sphere = methods.register_sphere();
+    let library = methods.register_primitive_library();
+    let material = methods.register_material();
+    let boxed = |center: [f32;3], half_size: f32, color: [f32;3]| Instance::primitive(library.ids(Primitive::Box), Primitive::Box, &[center[0], center[1], center[2], half_size, half_size, half_size], color);
+
+    let mut scene = SimpleScene::new();
+    scene.add_instance(SceneNode::group(CsgOp::Subtract, vec![
+        SceneNode::group(CsgOp::SmoothUnion(0.3), vec![
+            SceneNode::instance(Instance::sphere(sphere, [-2.0, 0.5, 7.0], 0.8, [0.9, 0.2, 0.2])),
+            SceneNode::instance(boxed([-2.0, -0.3, 7.0], 0.6, [0.2, 0.9, 0.2])),
+        ]),
+        SceneNode::instance(Instance::sphere(sphere, [-2.4, 0.6, 6.3], 0.5, [0.0; 3])),
+    ]));
+    scene.add_instance(
+        SceneNode::instance(boxed([0.0; 3], 0.5, [1.0, 0.6, 0.1]))
+            .transformed(Transform::scale([1.0, 1.5, 0.5]).with_rotation([0.0, 0.0, 1.0], 0.4))
+            .modified(DomainModifier::Twist{ strength: 0.8 })
+            .transformed(Transform::translate([0.0, 0.4, 7.0]))
+    );
+    scene.add_instance(
+        SceneNode::instance(Instance::sphere(sphere, [0.0; 3], 0.25, [0.3, 0.3, 1.0]))
+            .modified(DomainModifier::RepeatLimited{ period: [0.7, 0.0, 0.0], limit: [2.0, 0.0, 0.0] })
+            .animated(Animation::Keyframes{ keys: vec![(0.0, [0.0, 0.0, 0.0]), (1.0, [0.0, 1.0, 0.0])], looping: false })
+            .transformed(Transform::translate([0.0, -1.6, 6.0]))
+    );
+    scene.add_instance(Instance::sphere(sphere, [2.0, 0.0, 7.0], 0.9, [0.0; 3]).with_material(material, Material::glass([0.9, 1.0, 0.9], 1.5)));
+
+    let mut backend = backend(methods, scene);
+    backend.set_elapsed(0.5);
+    check_golden("framing", &backend);
+}
+
+/// Name and rom words of every parameter of `method` in `source`, after the first `skip` ones.
+fn glsl_params(source: &str, method: &str, skip: usize) -> Vec<(String, usize)>{
+    let start = source.find(&format!(" {}(", method)).unwrap_or_else(|| panic!("{} isn't defined", method)) + method.len() + 2;
+    let end = start + source[start..].find(')').unwrap();
+    source[start..end].split(',').skip(skip).map(|param|{
+        let mut words = param.split_whitespace().filter(|x| *x != "in");
+        let len = match words.next().unwrap(){
+            "float" | "int" => 1,
+            "vec3" | "ivec3" => 3,
+            "vec4" | "ivec4" => 4,
+            other => panic!("Unexpected parameter type {} in {}", other, method),
+        };
+        (words.next().unwrap().to_string(), len)
+    }).collect()
+}
+
+fn entries(deserializer: DataDeserializer) -> Vec<(String, usize)>{
+    deserializer.entries.iter().map(|x| (x.name.clone(), x.data_len())).collect()
+}
+
+/// The CPU ports read the rom with the layout of `Primitive::deserializer`, which the GLSL signatures have to agree with.
+#[test]
+fn ported_primitives_match_shader_signatures(){
+    for primitive in Primitive::ALL{
+        let source = primitive.source();
+        let name = primitive.name();
+        assert_eq!(glsl_params(source, &format!("sdf_{}", name), 1), entries(primitive.deserializer()), "sdf_{}", name);
+        assert_eq!(glsl_params(source, &format!("bound_{}", name), 2), entries(primitive.deserializer()), "bound_{}", name);
+        assert_eq!(glsl_params(source, &format!("color_{}", name), 1), entries(primitive.tex_deserializer()), "color_{}", name);
+    }
+}
@@ -0,0 +1,39 @@
+//! Parses scene descriptions for the headless renderer.
+
+use miniquad_raytrace::renderer::{
+    cpu::methods::CpuMethods,
+    scene::{Serializeable, SceneSerializer},
+    scene_file::SceneFile,
+    background::Background,
+};
+
+fn parse(source: &str) -> Result<SceneFile, String>{
+    let library = CpuMethods::new().register_primitive_library();
+    SceneFile::parse(source, &library).map_err(|e| e.to_string())
+}
+
+#[test]
+fn example_scene_parses(){
+    let mut file = parse(include_str!("../scenes/example.scene")).unwrap();
+    assert_eq!(file.camera, [0.0, 0.5, -1.0]);
+    assert_eq!(file.bounce_depth, Some(2));
+    assert!(matches!(file.background, Some(Background::Gradient{ .. })));
+    assert_eq!(file.nodes.len(), 4);
+
+    let mut rom = vec![0; 1024];
+    let mut serializer = SceneSerializer::new(&mut rom);
+    file.take_scene().serialize(&mut serializer);
+    assert!(serializer.position() > 0);
+    assert!(file.nodes.is_empty());
+}
+
+#[test]
+fn errors_name_the_line(){
+    let error = |source: &str| parse(source).err().unwrap();
+    assert_eq!(error("fog 0.1\nbox 0 0 0 1 1"), "line 2: Expected 6 numbers, found 5 for box");
+    assert_eq!(error("union {\n  torus 0 0 0 1 0.2\n"), "line 1: Block is never closed");
+    assert_eq!(error("}"), "line 1: '}' without an open block");
+    assert_eq!(error("# comment\n\nteapot 1 2 3"), "line 3: Unknown statement 'teapot'");
+    assert_eq!(error("smooth_union {\n}"), "line 1: Expected 1 numbers before '{' of smooth_union");
+    assert_eq!(error("scale 1 0 1 {\n}"), "line 1: Scale components have to be positive");
+}